@@ -1,9 +1,12 @@
+mod worker;
+
 use env_logger::Builder;
 use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
 use gtk::prelude::*;
 use ray_tracing::{
-    render, timeit, Camera, ColourChannel, Point, Sphere, BURNT_ORANGE,
-    IMG_SIZE, ZIMA_BLUE,
+    display, srgb_encode, timeit, Camera, ColourChannel, HdrImage, Integrator,
+    LightSource, ReconstructionFilter, SamplePattern, Scene, ToneMapOperator,
+    ToneMapping,
 };
 use relm4::{
     send, set_global_css_from_file, AppUpdate, Model, RelmApp, Sender,
@@ -11,48 +14,51 @@ use relm4::{
 };
 use std::path::Path;
 use tracker::track;
+use worker::RenderWorker;
 
 // Render time higher than 40ms means a framerate less than 25fps
 const RENDER_WARN_MS: u128 = 40;
 const CAMERA_WARN_MS: u128 = 1;
 
+// Largest image the resolution controls allow, 4K UHD
+const MAX_IMG_WIDTH: f64 = 3840.0;
+const MAX_IMG_HEIGHT: f64 = 2160.0;
+
+// How far from the origin shapes and lights can be moved
+const SCENE_SIZE: f64 = 1000.0;
+
+// Most rays the anti-aliasing controls allow per pixel
+const MAX_SAMPLES: f64 = 64.0;
+
+// How far each press of a camera movement button moves it
+const CAMERA_STEP: f64 = 50.0;
+
 pub fn main() {
     setup_logging();
+    let camera = Camera::default();
+    let (width, height) = (camera.img_width(), camera.img_height());
     let mut model = AppModel {
-        shapes: vec![
-            Sphere::default(),
-            Sphere::default_with_pos(Point::new(100.0, 100.0, 200.0)),
-            Sphere::default_with_pos(Point::new(200.0, 200.0, 400.0)),
-            Sphere::new_with_colour(
-                Point::new(-150.0, -50.0, 200.0),
-                50.0,
-                ZIMA_BLUE,
-            ),
-            Sphere::new_with_colour(
-                Point::new(34.0, 100.0, -150.0),
-                50.0,
-                BURNT_ORANGE,
-            ),
-        ],
-        camera: Camera::default(),
-        image: Pixbuf::new(
-            Colorspace::Rgb,
-            true,
-            8,
-            IMG_SIZE as i32,
-            IMG_SIZE as i32,
-        )
-        .unwrap(),
+        scene: Scene::demo(camera),
+        hdr: HdrImage::new(width, height),
+        worker: None,
+        tone_mapping: ToneMapping::default(),
+        image: new_pixbuf(width, height),
         current_index: 0,
+        current_light: 0,
         tracker: 0,
         is_light_selected: false,
+        fly_mode: false,
     };
-    model.render();
+    model.display();
     let app = RelmApp::new(model);
     set_global_css_from_file(Path::new("./src/app/resources/style.css"));
     app.run();
 }
 
+fn new_pixbuf(width: usize, height: usize) -> Pixbuf {
+    Pixbuf::new(Colorspace::Rgb, true, 8, width as i32, height as i32).unwrap()
+}
+
 fn setup_logging() {
     let mut log_builder = Builder::new();
     log_builder.filter_level(log::LevelFilter::Warn);
@@ -69,8 +75,29 @@ enum AppMsg {
     MoveX(f64),
     MoveY(f64),
     ResetCamera(RotationAxis),
+    MoveCamera(CameraMove),
+    SetFlyMode(bool),
     SetAmbient(f64),
+    SetShadows(bool),
+    SetReflectivity(f64),
+    SetTransparency(f64),
+    SetToneMapOperator(ToneMapOperator),
+    SetExposure(f64),
+    SetSrgb(bool),
+    SetSamples(usize),
+    SetSamplePattern(SamplePattern),
+    SetFilter(ReconstructionFilter),
+    SetIntegrator(Integrator),
+    SetWidth(usize),
+    SetHeight(usize),
     SelectLight,
+    CycleLight(isize),
+    AddLight,
+    RemoveLight,
+    /// Start the render worker and the first render
+    Start,
+    /// The render worker has a new frame to show
+    FrameReady,
 }
 
 #[derive(Debug)]
@@ -80,6 +107,17 @@ enum RotationAxis {
     Both,
 }
 
+/// Directions the camera buttons move it in, relative to the way it faces
+#[derive(Debug)]
+enum CameraMove {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
 #[derive(Debug)]
 enum Axis {
     X,
@@ -90,13 +128,25 @@ enum Axis {
 #[track]
 struct AppModel {
     #[tracker::do_not_track]
-    shapes: Vec<Sphere>,
+    scene: Scene,
+    /// Radiance from the last render, before it's quantised into `image`
+    #[tracker::do_not_track]
+    hdr: HdrImage,
+    /// Started by `AppMsg::Start` once the app can receive its frames
+    #[tracker::do_not_track]
+    worker: Option<RenderWorker>,
     #[tracker::do_not_track]
-    camera: Camera,
+    tone_mapping: ToneMapping,
     #[tracker::do_not_track]
     image: Pixbuf,
     current_index: usize,
+    #[tracker::do_not_track]
+    current_light: usize,
     is_light_selected: bool,
+    /// Whether the rotation controls turn the camera on the spot rather than
+    /// orbiting it around its target
+    #[tracker::do_not_track]
+    fly_mode: bool,
 }
 
 impl Model for AppModel {
@@ -106,17 +156,57 @@ impl Model for AppModel {
 }
 
 impl AppModel {
+    /// Start rendering the scene again in the background after it changes,
+    /// abandoning the frame that was in progress. The image is updated as
+    /// frames come back in `AppMsg::FrameReady`
     pub fn render(&mut self) {
-        let render_time = timeit!({
-            render(&mut self.image, &self.camera, &self.shapes);
-        })
-        .as_millis();
-        if render_time > RENDER_WARN_MS {
-            log::warn!("Render time: {render_time}ms");
-        } else {
-            log::info!("Render time: {render_time}ms");
+        if let Some(worker) = &mut self.worker {
+            worker.render(self.scene.clone());
         }
     }
+
+    /// Show the newest frame from the worker, if it's still of the current
+    /// scene
+    fn show_frame(&mut self) {
+        let Some(frame) = self.worker.as_ref().and_then(|w| w.take_frame())
+        else {
+            return;
+        };
+        if frame.passes == 1 {
+            let render_time = frame.pass_time.as_millis();
+            if render_time > RENDER_WARN_MS {
+                log::warn!("Render time: {render_time}ms");
+            } else {
+                log::info!("Render time: {render_time}ms");
+            }
+        }
+        self.hdr = frame.hdr;
+        self.display();
+    }
+
+    /// Render at a new resolution, replacing the displayed image with a
+    /// black one of the new size until the first frame arrives
+    fn resize(&mut self, width: usize, height: usize) {
+        self.scene.camera.set_resolution(width, height);
+        self.hdr = HdrImage::new(width, height);
+        self.image = new_pixbuf(width, height);
+        self.display();
+        self.render();
+    }
+
+    /// Tone map the last render into the displayed image, without tracing
+    /// the scene again
+    pub fn display(&mut self) {
+        display(&self.hdr, &mut self.image, &self.tone_mapping);
+    }
+
+    fn light(&self) -> &LightSource {
+        &self.scene.lights[self.current_light]
+    }
+
+    fn light_mut(&mut self) -> &mut LightSource {
+        &mut self.scene.lights[self.current_light]
+    }
 }
 
 impl AppUpdate for AppModel {
@@ -124,23 +214,26 @@ impl AppUpdate for AppModel {
         &mut self,
         msg: Self::Msg,
         _components: &Self::Components,
-        _sender: Sender<Self::Msg>,
+        sender: Sender<Self::Msg>,
     ) -> bool {
         self.reset();
         match msg {
             AppMsg::ChangePosition(axis, v) => {
                 let i = self.current_index;
                 if !self.is_light_selected {
+                    let shape = self.scene.shape_mut(i);
+                    let mut position = shape.position();
                     match axis {
-                        Axis::X => self.shapes[i].set_x(v),
-                        Axis::Y => self.shapes[i].set_y(v),
-                        Axis::Z => self.shapes[i].set_z(v),
+                        Axis::X => position.x = v,
+                        Axis::Y => position.y = v,
+                        Axis::Z => position.z = v,
                     }
+                    shape.set_position(position);
                 } else {
                     match axis {
-                        Axis::X => self.camera.light_source.set_x(v),
-                        Axis::Y => self.camera.light_source.set_y(v),
-                        Axis::Z => self.camera.light_source.set_z(v),
+                        Axis::X => self.light_mut().set_x(v),
+                        Axis::Y => self.light_mut().set_y(v),
+                        Axis::Z => self.light_mut().set_z(v),
                     }
                 }
                 self.render();
@@ -148,16 +241,19 @@ impl AppUpdate for AppModel {
             AppMsg::AdjustRadius(delta) => {
                 let i = self.current_index;
                 if !self.is_light_selected {
-                    self.shapes[i].adjust_radius(delta);
+                    self.scene.shape_mut(i).adjust_size(delta);
                 }
                 self.render();
             }
             AppMsg::ChangeColour(channel, new_colour) => {
                 let i = self.current_index;
                 if !self.is_light_selected {
-                    self.shapes[i].set_colour_channel(&channel, new_colour as u8);
+                    self.scene
+                        .shape_mut(i)
+                        .set_colour_channel(&channel, new_colour.round() as u8);
                 } else {
-                    self.camera.light_source.set_colour_channel(&channel, new_colour as u8);
+                    self.light_mut()
+                        .set_colour_channel(&channel, new_colour.round() as u8);
                 }
                 self.render();
             }
@@ -173,9 +269,35 @@ impl AppUpdate for AppModel {
                 self.is_light_selected = true;
                 self.tracker += 1;
             }
+            AppMsg::CycleLight(step) => {
+                let n = self.scene.lights.len() as isize;
+                let i = (self.current_light as isize + step).rem_euclid(n);
+                self.current_light = i as usize;
+                self.tracker += 1;
+            }
+            AppMsg::AddLight => {
+                self.scene.add_light(LightSource::default());
+                self.current_light = self.scene.lights.len() - 1;
+                self.tracker += 1;
+                self.render();
+            }
+            AppMsg::RemoveLight => {
+                // Always leave at least one light to select
+                if self.scene.lights.len() > 1 {
+                    self.scene.remove_light(self.current_light);
+                    self.current_light =
+                        self.current_light.min(self.scene.lights.len() - 1);
+                    self.tracker += 1;
+                    self.render();
+                }
+            }
             AppMsg::MoveX(x) => {
                 let camera_setup_time = timeit!({
-                    self.camera.move_x(x);
+                    if self.fly_mode {
+                        self.scene.camera.pan(x);
+                    } else {
+                        self.scene.camera.move_x(x);
+                    }
                 })
                 .as_millis();
                 if camera_setup_time > CAMERA_WARN_MS {
@@ -187,7 +309,11 @@ impl AppUpdate for AppModel {
             }
             AppMsg::MoveY(y) => {
                 let camera_setup_time = timeit!({
-                    self.camera.move_y(y);
+                    if self.fly_mode {
+                        self.scene.camera.tilt(y);
+                    } else {
+                        self.scene.camera.move_y(y);
+                    }
                 })
                 .as_millis();
                 if camera_setup_time > CAMERA_WARN_MS {
@@ -200,28 +326,111 @@ impl AppUpdate for AppModel {
             AppMsg::ResetCamera(axis) => {
                 match axis {
                     RotationAxis::Horizontal => {
-                        self.camera.reset_x();
+                        self.scene.camera.reset_x();
                     }
                     RotationAxis::Vertical => {
-                        self.camera.reset_y();
+                        self.scene.camera.reset_y();
                     }
                     RotationAxis::Both => {
-                        self.camera.reset_vrp();
+                        self.scene.camera.reset_vrp();
                     }
                 }
                 self.render();
             }
+            AppMsg::MoveCamera(direction) => {
+                let camera = &mut self.scene.camera;
+                match direction {
+                    // Flying carries the target along, otherwise the camera
+                    // closes in on it
+                    CameraMove::Forward if self.fly_mode => {
+                        camera.fly(CAMERA_STEP, 0.0, 0.0)
+                    }
+                    CameraMove::Back if self.fly_mode => {
+                        camera.fly(-CAMERA_STEP, 0.0, 0.0)
+                    }
+                    CameraMove::Forward => camera.dolly(CAMERA_STEP),
+                    CameraMove::Back => camera.dolly(-CAMERA_STEP),
+                    CameraMove::Left => camera.truck(-CAMERA_STEP, 0.0),
+                    CameraMove::Right => camera.truck(CAMERA_STEP, 0.0),
+                    CameraMove::Up => camera.truck(0.0, CAMERA_STEP),
+                    CameraMove::Down => camera.truck(0.0, -CAMERA_STEP),
+                }
+                self.render();
+            }
+            AppMsg::SetFlyMode(enabled) => self.fly_mode = enabled,
             AppMsg::SetAmbient(v) => {
-                self.camera.set_ambient_coefficient(v);
+                self.scene.camera.set_ambient_coefficient(v);
+                self.render()
+            }
+            AppMsg::SetShadows(enabled) => {
+                self.scene.settings.shadows = enabled;
                 self.render()
             }
+            AppMsg::SetReflectivity(v) => {
+                let i = self.current_index;
+                if !self.is_light_selected {
+                    self.scene.shape_mut(i).set_reflectivity(v);
+                    self.render();
+                }
+            }
+            AppMsg::SetTransparency(v) => {
+                let i = self.current_index;
+                if !self.is_light_selected {
+                    self.scene.shape_mut(i).set_transparency(v);
+                    self.render();
+                }
+            }
+            AppMsg::SetToneMapOperator(operator) => {
+                self.tone_mapping.operator = operator;
+                self.display();
+            }
+            AppMsg::SetExposure(v) => {
+                self.tone_mapping.exposure = v;
+                self.display();
+            }
+            AppMsg::SetSrgb(enabled) => {
+                self.tone_mapping.srgb = enabled;
+                self.display();
+            }
+            AppMsg::SetSamples(samples) => {
+                self.scene.settings.samples_per_pixel = samples;
+                self.render();
+            }
+            AppMsg::SetSamplePattern(sample_pattern) => {
+                self.scene.settings.sample_pattern = sample_pattern;
+                self.render();
+            }
+            AppMsg::SetFilter(filter) => {
+                self.scene.settings.filter = filter;
+                self.render();
+            }
+            AppMsg::SetIntegrator(integrator) => {
+                self.scene.settings.integrator = integrator;
+                self.render();
+            }
+            AppMsg::SetWidth(width) => {
+                let height = self.scene.camera().img_height();
+                self.resize(width, height);
+            }
+            AppMsg::SetHeight(height) => {
+                let width = self.scene.camera().img_width();
+                self.resize(width, height);
+            }
+            AppMsg::Start => {
+                self.worker = Some(RenderWorker::spawn(move || {
+                    // Nothing's listening any more once the app has closed
+                    let _ = sender.send(AppMsg::FrameReady);
+                }));
+                self.render();
+            }
+            AppMsg::FrameReady => self.show_frame(),
         }
         true
     }
 }
 
-const UPPER_BOUND_POS: f64 = (IMG_SIZE as f64) / 2.0;
-const LOWER_BOUND_POS: f64 = -((IMG_SIZE as f64) / 2.0);
+const UPPER_BOUND_POS: f64 = SCENE_SIZE / 2.0;
+const LOWER_BOUND_POS: f64 = -(SCENE_SIZE / 2.0);
 
 // This code is disgusting and should never be seen
 #[relm4::widget]
@@ -308,9 +517,9 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                     set_value: track!(
                                         model.changed(AppModel::current_index()),
                                         if !model.is_light_selected {
-                                            model.shapes[model.current_index].material.colour.x * 255.0
+                                            srgb_encode(model.scene.shape(model.current_index).material().colour.x) * 255.0
                                         } else {
-                                            model.camera.light_source.colour.x * 255.0
+                                            srgb_encode(model.light().colour.x) * 255.0
                                         }
                                     ),
                                     connect_value_changed[
@@ -329,9 +538,9 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                     set_value: track!(
                                         model.changed(AppModel::current_index()),
                                         if !model.is_light_selected {
-                                            model.shapes[model.current_index].material.colour.y * 255.0
+                                            srgb_encode(model.scene.shape(model.current_index).material().colour.y) * 255.0
                                         } else {
-                                            model.camera.light_source.colour.y * 255.0
+                                            srgb_encode(model.light().colour.y) * 255.0
                                         }
                                     ),
                                     connect_value_changed[
@@ -350,9 +559,9 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                     set_value: track!(
                                         model.changed(AppModel::current_index()),
                                         if !model.is_light_selected {
-                                            model.shapes[model.current_index].material.colour.z * 255.0
+                                            srgb_encode(model.scene.shape(model.current_index).material().colour.z) * 255.0
                                         } else {
-                                            model.camera.light_source.colour.z * 255.0
+                                            srgb_encode(model.light().colour.z) * 255.0
                                         }
                                     ),
                                     connect_value_changed[
@@ -384,7 +593,7 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                 set_label: watch! {
                                     &format!(
                                         "Radius: {}",
-                                        model.shapes[model.current_index].radius
+                                        model.scene.shape(model.current_index).size()
                                     )
                                 }
                             },
@@ -425,11 +634,11 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                 set_value: track!(
                                     model.changed(AppModel::current_index()),
                                     if !model.is_light_selected {
-                                        model.shapes[model.current_index].center.x
+                                        model.scene.shape(model.current_index).position().x
                                     } else {
-                                        model.camera.light_source.position.x
+                                        model.light().position.x
                                     }
-                                    // model.shapes[model.current_index].center.x
+                                    // model.scene.shape(model.current_index).position().x
                                 ),
                                 connect_value_changed[
                                     sender: Sender<AppMsg> = sender.clone(),
@@ -453,11 +662,11 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                 set_value: track!(
                                     model.changed(AppModel::current_index()),
                                     if !model.is_light_selected {
-                                        model.shapes[model.current_index].center.y
+                                        model.scene.shape(model.current_index).position().y
                                     } else {
-                                        model.camera.light_source.position.y
+                                        model.light().position.y
                                     }
-                                    // model.shapes[model.current_index].center.y
+                                    // model.scene.shape(model.current_index).position().y
                                 ),
                                 connect_value_changed[
                                     sender: Sender<AppMsg> = sender.clone(),
@@ -481,11 +690,11 @@ impl Widgets<AppModel, ()> for AppWidgets {
                                 set_value: track!(
                                     model.changed(AppModel::current_index()),
                                     if !model.is_light_selected {
-                                        model.shapes[model.current_index].center.z
+                                        model.scene.shape(model.current_index).position().z
                                     } else {
-                                        model.camera.light_source.position.z
+                                        model.light().position.z
                                     }
-                                    // model.shapes[model.current_index].center.z
+                                    // model.scene.shape(model.current_index).position().z
                                 ),
                                 connect_value_changed[
                                     sender: Sender<AppMsg> = sender.clone(),
@@ -497,6 +706,51 @@ impl Widgets<AppModel, ()> for AppWidgets {
                         },
                     },
 
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append = &gtk::Label {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: "Light Controls",
+                    },
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append: light_controls = &gtk::Box {
+                        set_halign: gtk::Align::Center,
+                        set_orientation: gtk::Orientation::Horizontal,
+                        append = &gtk::Button {
+                            set_label: "<",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::CycleLight(-1));
+                            },
+                        },
+                        append = &gtk::Label {
+                            set_margin_all: 5,
+                            set_label: watch! {
+                                &format!(
+                                    "Light {} of {}",
+                                    model.current_light + 1,
+                                    model.scene.lights.len(),
+                                )
+                            }
+                        },
+                        append = &gtk::Button {
+                            set_label: ">",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::CycleLight(1));
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Add Light",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::AddLight);
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Remove Light",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::RemoveLight);
+                            },
+                        },
+                    },
                     append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
                     append = &gtk::Label {
                         set_margin_all: 5,
@@ -543,12 +797,15 @@ impl Widgets<AppModel, ()> for AppWidgets {
                         set_margin_all: 5,
                         set_label: watch! {
                             &format!(
-                                "Camera Coords ({:.0}, {:.0}, {:.0})\nRotation: ({:.0}, {:.0})",
-                                model.camera.vrp().x,
-                                model.camera.vrp().y,
-                                model.camera.vrp().z,
-                                model.camera.h_rotation(),
-                                model.camera.v_rotation(),
+                                "Camera Coords ({:.0}, {:.0}, {:.0})\nTarget ({:.0}, {:.0}, {:.0})\nRotation: ({:.0}, {:.0})",
+                                model.scene.camera.vrp().x,
+                                model.scene.camera.vrp().y,
+                                model.scene.camera.vrp().z,
+                                model.scene.camera.target().x,
+                                model.scene.camera.target().y,
+                                model.scene.camera.target().z,
+                                model.scene.camera.h_rotation(),
+                                model.scene.camera.v_rotation(),
                             )
                         }
                     },
@@ -574,6 +831,54 @@ impl Widgets<AppModel, ()> for AppWidgets {
                             },
                         },
                     },
+                    append: move_buttons = &gtk::Box {
+                        set_halign: gtk::Align::Center,
+                        set_orientation: gtk::Orientation::Horizontal,
+                        append = &gtk::Button {
+                            set_label: "Forward",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::MoveCamera(CameraMove::Forward));
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Back",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::MoveCamera(CameraMove::Back));
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Left",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::MoveCamera(CameraMove::Left));
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Right",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::MoveCamera(CameraMove::Right));
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Up",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::MoveCamera(CameraMove::Up));
+                            },
+                        },
+                        append = &gtk::Button {
+                            set_label: "Down",
+                            connect_clicked(sender) => move |_| {
+                                send!(sender, AppMsg::MoveCamera(CameraMove::Down));
+                            },
+                        },
+                    },
+                    append = &gtk::CheckButton {
+                        set_label: Some("Fly (turn on the spot instead of orbiting)"),
+                        set_halign: gtk::Align::Center,
+                        set_active: false,
+                        connect_toggled(sender) => move |b| {
+                            send!(sender, AppMsg::SetFlyMode(b.is_active()));
+                        },
+                    },
 
                     append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
                     append = &gtk::Label {
@@ -588,13 +893,203 @@ impl Widgets<AppModel, ()> for AppWidgets {
                         set_range: args!(0.0, 1.0),
                         set_increments: args!(0.01, 0.01),
                         // set_slider_size_fixed: true,
-                        set_value: model.camera.ambient_coefficient(),
+                        set_value: model.scene.camera.ambient_coefficient(),
                         // set_size_request: args!(100, -1),
                         connect_value_changed(sender) => move |s| {
                             let v = s.value();
                             send!(sender, AppMsg::SetAmbient(v));
                         },
                     },
+
+                    append = &gtk::CheckButton {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: Some("Shadows"),
+                        set_active: model.scene.settings.shadows,
+                        connect_toggled(sender) => move |b| {
+                            send!(sender, AppMsg::SetShadows(b.is_active()));
+                        }
+                    },
+                    append = &gtk::DropDown::from_strings(
+                        &Integrator::ALL.map(|integrator| integrator.name())
+                    ) {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_tooltip_text: Some("Renderer"),
+                        set_selected: Integrator::ALL
+                            .iter()
+                            .position(|i| *i == model.scene.settings.integrator)
+                            .unwrap_or(0) as u32,
+                        connect_selected_notify(sender) => move |d| {
+                            let integrator = Integrator::ALL[d.selected() as usize];
+                            send!(sender, AppMsg::SetIntegrator(integrator));
+                        },
+                    },
+
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append = &gtk::Label {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: "Reflectivity",
+                    },
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+
+                    append: reflectivity_controls = &gtk::Scale {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_range: args!(0.0, 1.0),
+                        set_increments: args!(0.01, 0.01),
+                        set_value: track!(
+                            model.changed(AppModel::current_index()),
+                            model.scene.shape(model.current_index).material().reflectivity()
+                        ),
+                        connect_value_changed(sender) => move |s| {
+                            let v = s.value();
+                            send!(sender, AppMsg::SetReflectivity(v));
+                        },
+                    },
+
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append = &gtk::Label {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: "Transparency",
+                    },
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+
+                    append: transparency_controls = &gtk::Scale {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_range: args!(0.0, 1.0),
+                        set_increments: args!(0.01, 0.01),
+                        set_value: track!(
+                            model.changed(AppModel::current_index()),
+                            model.scene.shape(model.current_index).material().transparency()
+                        ),
+                        connect_value_changed(sender) => move |s| {
+                            let v = s.value();
+                            send!(sender, AppMsg::SetTransparency(v));
+                        },
+                    },
+
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append = &gtk::Label {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: "Tone Mapping",
+                    },
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+
+                    append = &gtk::DropDown::from_strings(
+                        &ToneMapOperator::ALL.map(|operator| operator.name())
+                    ) {
+                        set_margin_all: 5,
+                        connect_selected_notify(sender) => move |d| {
+                            let operator = ToneMapOperator::ALL[d.selected() as usize];
+                            send!(sender, AppMsg::SetToneMapOperator(operator));
+                        },
+                    },
+                    append = &gtk::Label {
+                        set_label: "Exposure",
+                    },
+                    append: exposure_controls = &gtk::Scale {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_range: args!(-5.0, 5.0),
+                        set_increments: args!(0.1, 1.0),
+                        set_value: model.tone_mapping.exposure,
+                        connect_value_changed(sender) => move |s| {
+                            let v = s.value();
+                            send!(sender, AppMsg::SetExposure(v));
+                        },
+                    },
+                    append = &gtk::CheckButton {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: Some("sRGB"),
+                        set_active: model.tone_mapping.srgb,
+                        connect_toggled(sender) => move |b| {
+                            send!(sender, AppMsg::SetSrgb(b.is_active()));
+                        }
+                    },
+
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append = &gtk::Label {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: "Anti-aliasing",
+                    },
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+
+                    append: anti_aliasing_controls = &gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_halign: gtk::Align::Center,
+                        set_margin_all: 5,
+                        set_spacing: 5,
+
+                        append = &gtk::SpinButton::with_range(1.0, MAX_SAMPLES, 1.0) {
+                            set_tooltip_text: Some("Samples per pixel"),
+                            set_value: model.scene.settings.samples_per_pixel as f64,
+                            connect_value_changed(sender) => move |s| {
+                                let samples = s.value_as_int() as usize;
+                                send!(sender, AppMsg::SetSamples(samples));
+                            },
+                        },
+                        append = &gtk::DropDown::from_strings(
+                            &SamplePattern::ALL.map(|pattern| pattern.name())
+                        ) {
+                            set_selected: SamplePattern::ALL
+                                .iter()
+                                .position(|p| *p == model.scene.settings.sample_pattern)
+                                .unwrap_or(0) as u32,
+                            connect_selected_notify(sender) => move |d| {
+                                let pattern = SamplePattern::ALL[d.selected() as usize];
+                                send!(sender, AppMsg::SetSamplePattern(pattern));
+                            },
+                        },
+                        append = &gtk::DropDown::from_strings(
+                            &ReconstructionFilter::ALL.map(|filter| filter.name())
+                        ) {
+                            set_selected: ReconstructionFilter::ALL
+                                .iter()
+                                .position(|f| *f == model.scene.settings.filter)
+                                .unwrap_or(0) as u32,
+                            connect_selected_notify(sender) => move |d| {
+                                let filter = ReconstructionFilter::ALL[d.selected() as usize];
+                                send!(sender, AppMsg::SetFilter(filter));
+                            },
+                        },
+                    },
+
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+                    append = &gtk::Label {
+                        set_margin_all: 5,
+                        set_halign: gtk::Align::Center,
+                        set_label: "Resolution",
+                    },
+                    append = &gtk::Separator::new(gtk::Orientation::Horizontal) {},
+
+                    append: resolution_controls = &gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_halign: gtk::Align::Center,
+                        set_margin_all: 5,
+                        set_spacing: 5,
+
+                        append = &gtk::SpinButton::with_range(1.0, MAX_IMG_WIDTH, 1.0) {
+                            set_value: model.scene.camera().img_width() as f64,
+                            connect_value_changed(sender) => move |s| {
+                                let width = s.value_as_int() as usize;
+                                send!(sender, AppMsg::SetWidth(width));
+                            },
+                        },
+                        append = &gtk::Label {
+                            set_label: "x",
+                        },
+                        append = &gtk::SpinButton::with_range(1.0, MAX_IMG_HEIGHT, 1.0) {
+                            set_value: model.scene.camera().img_height() as f64,
+                            connect_value_changed(sender) => move |s| {
+                                let height = s.value_as_int() as usize;
+                                send!(sender, AppMsg::SetHeight(height));
+                            },
+                        },
+                    },
                 },
                 append = &gtk::Separator::new(gtk::Orientation::Vertical) {},
                 append: img = &gtk::Picture {
@@ -607,4 +1102,8 @@ impl Widgets<AppModel, ()> for AppWidgets {
             }
         }
     }
+
+    fn post_init() {
+        send!(sender, AppMsg::Start);
+    }
 }
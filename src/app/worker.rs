@@ -0,0 +1,124 @@
+use ray_tracing::{
+    render_pass_cancellable, AccumulationBuffer, CancelToken, HdrImage, Scene,
+};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Passes accumulated before the image is considered finished
+const MAX_PASSES: usize = 256;
+
+/// The image so far from one of the worker's renders
+pub struct Frame {
+    pub hdr: HdrImage,
+    /// How many passes have been averaged into `hdr`
+    pub passes: usize,
+    /// How long the latest pass took
+    pub pass_time: Duration,
+    generation: usize,
+}
+
+struct Job {
+    scene: Scene,
+    generation: usize,
+    cancel: CancelToken,
+}
+
+/// Renders on a background thread so the UI stays responsive, refining the
+/// image pass by pass until it's finished or a newer scene replaces it
+pub struct RenderWorker {
+    jobs: mpsc::Sender<Job>,
+    cancel: CancelToken,
+    generation: usize,
+    /// Only the newest frame is kept, so the worker never gets ahead of the
+    /// UI by more than one frame
+    latest: Arc<Mutex<Option<Frame>>>,
+}
+
+impl RenderWorker {
+    /// Start the worker thread. `frame_ready` is called on that thread each
+    /// time there's a new frame to pick up with `take_frame`
+    pub fn spawn(frame_ready: impl Fn() + Send + 'static) -> Self {
+        let (jobs, receiver) = mpsc::channel();
+        let latest = Arc::new(Mutex::new(None));
+        let worker_latest = Arc::clone(&latest);
+        thread::Builder::new()
+            .name("render worker".to_string())
+            .spawn(move || run(receiver, &worker_latest, frame_ready))
+            .expect("couldn't start the render worker");
+        Self {
+            jobs,
+            cancel: CancelToken::new(),
+            generation: 0,
+            latest,
+        }
+    }
+
+    /// Abandon whatever is being rendered and start again on `scene`
+    pub fn render(&mut self, scene: Scene) {
+        self.cancel.cancel();
+        self.cancel = CancelToken::new();
+        self.generation += 1;
+        let job = Job {
+            scene,
+            generation: self.generation,
+            cancel: self.cancel.clone(),
+        };
+        // The worker only stops if this is dropped, so it's always listening
+        self.jobs.send(job).expect("render worker has stopped");
+    }
+
+    /// The newest frame of the latest scene, if there's one which hasn't
+    /// been taken yet. Frames still in flight from abandoned renders are
+    /// thrown away
+    pub fn take_frame(&self) -> Option<Frame> {
+        self.latest
+            .lock()
+            .unwrap()
+            .take()
+            .filter(|frame| frame.generation == self.generation)
+    }
+}
+
+impl Drop for RenderWorker {
+    fn drop(&mut self) {
+        // The thread finishes once it's stopped and the channel has closed
+        self.cancel.cancel();
+    }
+}
+
+fn run(
+    jobs: Receiver<Job>,
+    latest: &Mutex<Option<Frame>>,
+    frame_ready: impl Fn(),
+) {
+    let mut accumulation = AccumulationBuffer::new(0, 0);
+    let mut hdr = HdrImage::new(0, 0);
+    while let Ok(mut job) = jobs.recv() {
+        // Skip straight to the newest scene if several changes came in
+        // during the last render
+        while let Ok(newer) = jobs.try_recv() {
+            job = newer;
+        }
+        accumulation.clear();
+        while accumulation.passes() < MAX_PASSES {
+            let start = Instant::now();
+            if !render_pass_cancellable(
+                &mut accumulation,
+                &job.scene,
+                &job.cancel,
+            ) {
+                break;
+            }
+            accumulation.resolve_into(&mut hdr);
+            *latest.lock().unwrap() = Some(Frame {
+                hdr: hdr.clone(),
+                passes: accumulation.passes(),
+                pass_time: start.elapsed(),
+                generation: job.generation,
+            });
+            frame_ready();
+        }
+    }
+}
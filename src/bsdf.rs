@@ -0,0 +1,333 @@
+use crate::{
+    cosine_hemisphere, cosine_hemisphere_pdf, LightColour, LightSample,
+    Surface, Vector3D,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{PI, TAU};
+
+/// Smallest GGX alpha. A perfectly smooth surface's distribution is a spike
+/// which can't be evaluated
+const MIN_ALPHA: f64 = 1e-3;
+
+/// Parameters of the physically based metallic-roughness material model
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Microfacet {
+    /// From 0.0 for non-metals like plastic to 1.0 for bare metal. Metals
+    /// have no diffuse reflection and tint their highlights with their
+    /// colour
+    pub metallic: f64,
+    /// From 0.0 (polished) to 1.0 (completely rough)
+    pub roughness: f64,
+}
+
+impl Default for Microfacet {
+    fn default() -> Self {
+        Self {
+            metallic: 0.0,
+            roughness: 0.5,
+        }
+    }
+}
+
+/// A direction picked by `Bsdf::sample`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BsdfSample {
+    /// Unit vector from the surface in the direction light arrives from
+    pub direction: Vector3D,
+    /// The BSDF times the cosine of `direction` with the normal, over `pdf`
+    pub weight: LightColour,
+    /// Probability density of picking `direction`, per unit solid angle
+    pub pdf: f64,
+}
+
+/// How a surface scatters the light arriving from one direction into
+/// another, set up at a point on it. Directions are unit vectors pointing
+/// away from the surface
+#[derive(Copy, Clone, Debug)]
+pub struct Bsdf {
+    /// Surface normal, on the side light is scattered on
+    normal: Vector3D,
+    model: Model,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Model {
+    /// Ideal diffuse reflection, used for Phong materials
+    Lambertian { albedo: LightColour },
+    /// GGX microfacet reflection with Smith shadowing and Schlick's Fresnel,
+    /// over a diffuse base which gets whatever light isn't reflected
+    Microfacet {
+        diffuse: LightColour,
+        f0: LightColour,
+        alpha: f64,
+    },
+}
+
+impl Bsdf {
+    /// The BSDF of a surface, scattering light on the side `normal` faces
+    pub fn new(surface: &Surface, normal: Vector3D) -> Self {
+        let colour = surface.colour;
+        let model = match surface.microfacet {
+            None => Model::Lambertian { albedo: colour },
+            Some(Microfacet {
+                metallic,
+                roughness,
+            }) => Model::Microfacet {
+                diffuse: colour * (1.0 - metallic),
+                f0: surface.specular,
+                alpha: (roughness * roughness).max(MIN_ALPHA),
+            },
+        };
+        Self { normal, model }
+    }
+
+    /// Fraction of the light arriving from `incoming` which is scattered
+    /// towards `outgoing`, per unit solid angle
+    pub fn eval(
+        &self,
+        outgoing: &Vector3D,
+        incoming: &Vector3D,
+    ) -> LightColour {
+        let cos_o = outgoing.dot(&self.normal);
+        let cos_i = incoming.dot(&self.normal);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return LightColour::default();
+        }
+        match self.model {
+            Model::Lambertian { albedo } => albedo / PI,
+            Model::Microfacet { diffuse, f0, alpha } => {
+                let half = half_vector(outgoing, incoming);
+                let fresnel = schlick(f0, outgoing.dot(&half));
+                let specular = fresnel
+                    * (ggx(self.normal.dot(&half), alpha)
+                        * smith(cos_o, alpha)
+                        * smith(cos_i, alpha)
+                        / (4.0 * cos_o * cos_i));
+                let transmitted = LightColour::new(1.0, 1.0, 1.0) - fresnel;
+                diffuse.mul(&transmitted) / PI + specular
+            }
+        }
+    }
+
+    /// Probability density of `sample` picking `incoming` when light is
+    /// scattered towards `outgoing`
+    pub fn pdf(&self, outgoing: &Vector3D, incoming: &Vector3D) -> f64 {
+        let cos_o = outgoing.dot(&self.normal);
+        let cos_i = incoming.dot(&self.normal);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+        let diffuse_pdf = cosine_hemisphere_pdf(cos_i);
+        match self.model {
+            Model::Lambertian { .. } => diffuse_pdf,
+            Model::Microfacet { alpha, .. } => {
+                let half = half_vector(outgoing, incoming);
+                let cos_h = self.normal.dot(&half);
+                let specular_pdf =
+                    ggx(cos_h, alpha) * cos_h / (4.0 * outgoing.dot(&half));
+                let p = self.specular_probability();
+                p * specular_pdf + (1.0 - p) * diffuse_pdf
+            }
+        }
+    }
+
+    /// Pick a direction for light scattered towards `outgoing` to have come
+    /// from, roughly in proportion to how much it contributes. `None` if the
+    /// direction picked is below the surface
+    pub fn sample(
+        &self,
+        outgoing: &Vector3D,
+        rng: &mut impl Rng,
+    ) -> Option<BsdfSample> {
+        if outgoing.dot(&self.normal) <= 0.0 {
+            return None;
+        }
+        let u = (rng.gen(), rng.gen());
+        let direction = match self.model {
+            Model::Microfacet { alpha, .. }
+                if rng.gen::<f64>() < self.specular_probability() =>
+            {
+                let half = self.ggx_half_vector(alpha, u);
+                (*outgoing * -1.0).reflect(&half)
+            }
+            _ => cosine_hemisphere(&self.normal, u),
+        };
+        let pdf = self.pdf(outgoing, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let cos = direction.dot(&self.normal);
+        Some(BsdfSample {
+            direction,
+            weight: self.eval(outgoing, &direction) * (cos / pdf),
+            pdf,
+        })
+    }
+
+    /// Light from a light source sample scattered towards `outgoing`. Light
+    /// colours are set so that a white diffuse surface facing them is lit to
+    /// their colour, as in Phong shading, so they give π times it in
+    /// irradiance
+    pub fn scatter_light(
+        &self,
+        outgoing: &Vector3D,
+        sample: &LightSample,
+    ) -> LightColour {
+        let cos = sample.direction.dot(&self.normal).max(0.0);
+        self.eval(outgoing, &sample.direction).mul(&sample.colour) * (PI * cos)
+    }
+
+    /// How often `sample` picks the specular lobe rather than the diffuse
+    /// one, in proportion to how bright each is
+    fn specular_probability(&self) -> f64 {
+        match self.model {
+            Model::Lambertian { .. } => 0.0,
+            Model::Microfacet { diffuse, f0, .. } => {
+                let (specular, diffuse) = (mean(f0), mean(diffuse));
+                if specular + diffuse > 0.0 {
+                    specular / (specular + diffuse)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Microfacet normal picked in proportion to its share of the GGX
+    /// distribution, times its cosine with the surface normal
+    fn ggx_half_vector(&self, alpha: f64, u: (f64, f64)) -> Vector3D {
+        let cos2 = (1.0 - u.0) / (1.0 + (alpha * alpha - 1.0) * u.0);
+        let (cos, sin) = (cos2.sqrt(), (1.0 - cos2).max(0.0).sqrt());
+        let phi = TAU * u.1;
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        tangent * (sin * phi.cos())
+            + bitangent * (sin * phi.sin())
+            + self.normal * cos
+    }
+}
+
+fn half_vector(outgoing: &Vector3D, incoming: &Vector3D) -> Vector3D {
+    let mut half = *outgoing + *incoming;
+    half.normalise();
+    half
+}
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals, given the
+/// cosine of one with the surface normal
+fn ggx(cos: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let d = cos * cos * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Smith's masking function for GGX: the fraction of microfacets facing a
+/// direction at `cos` to the normal which aren't hidden by others
+fn smith(cos: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+}
+
+/// Schlick's approximation of the Fresnel reflectance
+fn schlick(f0: LightColour, cos: f64) -> LightColour {
+    let white = LightColour::new(1.0, 1.0, 1.0);
+    f0 + (white - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+fn mean(colour: LightColour) -> f64 {
+    (colour.x + colour.y + colour.z) / 3.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Material;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const SAMPLES: usize = 20000;
+
+    fn up() -> Vector3D {
+        Vector3D::new(0.0, 0.0, 1.0)
+    }
+
+    fn outgoing() -> Vector3D {
+        let mut outgoing = Vector3D::new(0.5, 0.0, 1.0);
+        outgoing.normalise();
+        outgoing
+    }
+
+    fn microfacet(colour: LightColour, metallic: f64, roughness: f64) -> Bsdf {
+        let material = Material::new(colour, 0.0).with_microfacet(Microfacet {
+            metallic,
+            roughness,
+        });
+        Bsdf::new(&material.at(&Vector3D::default(), (0.0, 0.0)), up())
+    }
+
+    #[test]
+    fn lambertian_sample_weight_is_albedo() {
+        let albedo = LightColour::new(0.2, 0.4, 0.6);
+        let surface =
+            Material::new(albedo, 0.0).at(&Vector3D::default(), (0.0, 0.0));
+        let bsdf = Bsdf::new(&surface, up());
+        let mut rng = StdRng::seed_from_u64(1);
+        let sample = bsdf.sample(&outgoing(), &mut rng).unwrap();
+        assert!((sample.weight - albedo).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn microfacet_is_reciprocal() {
+        let bsdf = microfacet(LightColour::new(0.8, 0.5, 0.2), 0.3, 0.4);
+        let mut incoming = Vector3D::new(-0.2, 0.7, 0.5);
+        incoming.normalise();
+        let there = bsdf.eval(&outgoing(), &incoming);
+        let back = bsdf.eval(&incoming, &outgoing());
+        assert!((there - back).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        // The mean of 1 / pdf over samples drawn from it is the area of the
+        // hemisphere, as long as the pdf covers all of it. Samples which end
+        // up below the surface count as 0
+        let bsdf = microfacet(LightColour::new(0.5, 0.5, 0.5), 0.0, 0.3);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut area = 0.0;
+        for _ in 0..SAMPLES {
+            if let Some(sample) = bsdf.sample(&outgoing(), &mut rng) {
+                let pdf = bsdf.pdf(&outgoing(), &sample.direction);
+                assert_eq!(sample.pdf, pdf);
+                area += 1.0 / pdf / SAMPLES as f64;
+            }
+        }
+        assert!((area - TAU).abs() < TAU * 0.05, "{area}");
+    }
+
+    /// Fraction of light reflected towards `outgoing()` by a white surface
+    fn white_albedo(metallic: f64, roughness: f64) -> f64 {
+        let white = LightColour::new(1.0, 1.0, 1.0);
+        let bsdf = microfacet(white, metallic, roughness);
+        let mut rng = StdRng::seed_from_u64(1);
+        (0..SAMPLES).fold(0.0, |total, _| {
+            let weight = bsdf
+                .sample(&outgoing(), &mut rng)
+                .map_or(0.0, |sample| sample.weight.x);
+            total + weight / SAMPLES as f64
+        })
+    }
+
+    #[test]
+    fn microfacet_conserves_energy() {
+        // Light bouncing between microfacets more than once is lost, so
+        // rough surfaces reflect less than everything
+        let polished = white_albedo(1.0, 0.2);
+        assert!(polished > 0.95 && polished < 1.01, "{polished}");
+        let rough = white_albedo(1.0, 0.8);
+        assert!(rough < polished, "{rough}");
+        // The diffuse base only gets light the coating doesn't reflect
+        let plastic = white_albedo(0.0, 0.5);
+        assert!(plastic < 1.0, "{plastic}");
+    }
+}
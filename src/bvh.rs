@@ -0,0 +1,486 @@
+use crate::{Intersection, Point, Ray, Vector3D};
+
+/// Leaves with this many items or fewer are never split
+const MAX_LEAF_SIZE: usize = 4;
+/// Number of buckets centroids are sorted into when estimating split costs
+const SAH_BUCKETS: usize = 12;
+/// Cost of traversing a node relative to testing a single shape
+const TRAVERSAL_COST: f64 = 0.125;
+
+/// Axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn new(min: Point, max: Point) -> Self {
+        Self { min, max }
+    }
+
+    /// A box containing nothing, which can be grown with `union`
+    pub fn empty() -> Self {
+        Self::new(
+            Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        )
+    }
+
+    /// A box containing everything, for shapes which go on forever
+    pub fn infinite() -> Self {
+        Self::new(
+            Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        )
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Point>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::empty(), |aabb, p| aabb.union(&Self::new(p, p)))
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.min.to_array().iter().all(|v| v.is_finite())
+            && self.max.to_array().iter().all(|v| v.is_finite())
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Self::new(
+            Point::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Point::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn extent(&self) -> Vector3D {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let e = self.extent();
+        if e.x < 0.0 || e.y < 0.0 || e.z < 0.0 {
+            0.0
+        } else {
+            2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+        }
+    }
+
+    /// Distance along the ray to where it enters the box, if it does so before
+    /// `t_max`. Uses the slab method with the ray's precomputed inverse
+    /// direction
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        inverse_direction: &Vector3D,
+        t_max: f64,
+    ) -> Option<f64> {
+        let mut t_near = 0.0_f64;
+        let mut t_far = t_max;
+        let origin = ray.origin.to_array();
+        let inverse = inverse_direction.to_array();
+        let min = self.min.to_array();
+        let max = self.max.to_array();
+        for axis in 0..3 {
+            let mut t0 = (min[axis] - origin[axis]) * inverse[axis];
+            let mut t1 = (max[axis] - origin[axis]) * inverse[axis];
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from 0 * inf (ray in the plane of a slab) is ignored by
+            // max/min, leaving the interval unchanged
+            t_near = t_near.max(t0);
+            t_far = t_far.min(t1);
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some(t_near)
+    }
+}
+
+#[derive(Clone)]
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } => bounds,
+            BvhNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over a set of items identified by index, built
+/// using the surface area heuristic
+#[derive(Clone)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    /// Build a hierarchy over `(index, bounding box)` pairs. The boxes should
+    /// all be bounded, infinite shapes need to be tested separately
+    pub fn new(items: Vec<(usize, Aabb)>) -> Self {
+        let mut bvh = Self {
+            nodes: vec![],
+            indices: Vec::with_capacity(items.len()),
+        };
+        let mut items = items
+            .into_iter()
+            .map(|(index, aabb)| (index, aabb, aabb.centroid()))
+            .collect::<Vec<_>>();
+        if !items.is_empty() {
+            bvh.build(&mut items);
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map(|n| *n.bounds())
+            .unwrap_or_else(Aabb::empty)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Find the closest intersection along `ray`, calling `intersect` with the
+    /// index of every item whose bounding box the ray passes through
+    pub fn closest_intersect<'a>(
+        &self,
+        ray: &Ray,
+        mut intersect: impl FnMut(usize) -> Option<Intersection<'a>>,
+    ) -> Option<Intersection<'a>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let d = ray.direction;
+        let inverse_direction = Vector3D::new(1.0 / d.x, 1.0 / d.y, 1.0 / d.z);
+        let mut closest: Option<Intersection<'a>> = None;
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let t_max = closest.map_or(f64::INFINITY, |c| c.t());
+            let node = &self.nodes[node_index];
+            if node.bounds().hit(ray, &inverse_direction, t_max).is_none() {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, count, .. } => {
+                    for &index in &self.indices[start..start + count] {
+                        if let Some(intersection) = intersect(index) {
+                            if closest.is_none_or(|c| intersection.t() < c.t())
+                            {
+                                closest = Some(intersection);
+                            }
+                        }
+                    }
+                }
+                BvhNode::Interior { left, right, .. } => {
+                    // Push the further child first so the nearer one is
+                    // visited first, giving a tighter t_max sooner
+                    let t_left = self.nodes[left].bounds().hit(
+                        ray,
+                        &inverse_direction,
+                        t_max,
+                    );
+                    let t_right = self.nodes[right].bounds().hit(
+                        ray,
+                        &inverse_direction,
+                        t_max,
+                    );
+                    match (t_left, t_right) {
+                        (Some(l), Some(r)) if l <= r => {
+                            stack.push(right);
+                            stack.push(left);
+                        }
+                        (Some(_), Some(_)) => {
+                            stack.push(left);
+                            stack.push(right);
+                        }
+                        (Some(_), None) => stack.push(left),
+                        (None, Some(_)) => stack.push(right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Whether any item is hit less than `t_max` along `ray`, calling `hits`
+    /// with the index of each item whose bounding box the ray passes through
+    /// until one of them returns true
+    pub fn any_hit(
+        &self,
+        ray: &Ray,
+        t_max: f64,
+        mut hits: impl FnMut(usize) -> bool,
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let d = ray.direction;
+        let inverse_direction = Vector3D::new(1.0 / d.x, 1.0 / d.y, 1.0 / d.z);
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.bounds().hit(ray, &inverse_direction, t_max).is_none() {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, count, .. } => {
+                    if self.indices[start..start + count]
+                        .iter()
+                        .any(|&index| hits(index))
+                    {
+                        return true;
+                    }
+                }
+                // Any hit will do, so the order children are visited in
+                // doesn't matter
+                BvhNode::Interior { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Recursively build nodes for `items`, returning the index of the node
+    fn build(&mut self, items: &mut [(usize, Aabb, Point)]) -> usize {
+        let bounds = items
+            .iter()
+            .fold(Aabb::empty(), |aabb, (_, item, _)| aabb.union(item));
+
+        let node_index = self.nodes.len();
+        match Self::split(items, &bounds) {
+            Some(mid) => {
+                // Placeholder, children are built before this node is complete
+                self.nodes.push(BvhNode::Leaf {
+                    bounds,
+                    start: 0,
+                    count: 0,
+                });
+                let (left_items, right_items) = items.split_at_mut(mid);
+                let left = self.build(left_items);
+                let right = self.build(right_items);
+                self.nodes[node_index] = BvhNode::Interior {
+                    bounds,
+                    left,
+                    right,
+                };
+            }
+            None => {
+                let start = self.indices.len();
+                self.indices.extend(items.iter().map(|(index, _, _)| index));
+                self.nodes.push(BvhNode::Leaf {
+                    bounds,
+                    start,
+                    count: items.len(),
+                });
+            }
+        }
+        node_index
+    }
+
+    /// Partition `items` along the cheapest split according to the surface
+    /// area heuristic, returning where the second half starts. Returns `None`
+    /// if the items are cheaper to leave in a single leaf
+    fn split(
+        items: &mut [(usize, Aabb, Point)],
+        bounds: &Aabb,
+    ) -> Option<usize> {
+        if items.len() <= MAX_LEAF_SIZE {
+            return None;
+        }
+        let centroid_bounds =
+            Aabb::from_points(items.iter().map(|(_, _, c)| *c));
+        let centroid_min = centroid_bounds.min.to_array();
+        let centroid_extent = centroid_bounds.extent().to_array();
+        let parent_area = bounds.surface_area();
+
+        // (cost, axis, bucket boundary)
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            if centroid_extent[axis] <= 0.0 {
+                continue;
+            }
+            let bucket_of = |c: &Point| {
+                let offset = (c.to_array()[axis] - centroid_min[axis])
+                    / centroid_extent[axis];
+                ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+            };
+            let mut counts = [0usize; SAH_BUCKETS];
+            let mut boxes = [Aabb::empty(); SAH_BUCKETS];
+            for (_, aabb, centroid) in items.iter() {
+                let b = bucket_of(centroid);
+                counts[b] += 1;
+                boxes[b] = boxes[b].union(aabb);
+            }
+            for boundary in 1..SAH_BUCKETS {
+                let (left_count, left_box) =
+                    Self::sum_buckets(&counts[..boundary], &boxes[..boundary]);
+                let (right_count, right_box) =
+                    Self::sum_buckets(&counts[boundary..], &boxes[boundary..]);
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + (left_count as f64 * left_box.surface_area()
+                        + right_count as f64 * right_box.surface_area())
+                        / parent_area;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, boundary));
+                }
+            }
+        }
+
+        let (cost, axis, boundary) = best?;
+        if cost >= items.len() as f64 {
+            return None;
+        }
+        let boundary_value = centroid_min[axis]
+            + centroid_extent[axis] * boundary as f64 / SAH_BUCKETS as f64;
+        items.sort_unstable_by(|a, b| {
+            a.2.to_array()[axis].total_cmp(&b.2.to_array()[axis])
+        });
+        let mid = items
+            .partition_point(|(_, _, c)| c.to_array()[axis] < boundary_value);
+        if mid == 0 || mid == items.len() {
+            Some(items.len() / 2)
+        } else {
+            Some(mid)
+        }
+    }
+
+    fn sum_buckets(counts: &[usize], boxes: &[Aabb]) -> (usize, Aabb) {
+        counts
+            .iter()
+            .zip(boxes)
+            .fold((0, Aabb::empty()), |(count, aabb), (c, b)| {
+                (count + c, aabb.union(b))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box_at(x: f64) -> Aabb {
+        Aabb::new(Point::new(x, 0.0, 0.0), Point::new(x + 1.0, 1.0, 1.0))
+    }
+
+    fn ray_along_x(y: f64) -> (Ray, Vector3D) {
+        let ray = Ray {
+            origin: Point::new(-10.0, y, 0.5),
+            direction: Vector3D::new(1.0, 0.0, 0.0),
+        };
+        let inverse = Vector3D::new(1.0, f64::INFINITY, f64::INFINITY);
+        (ray, inverse)
+    }
+
+    #[test]
+    fn ray_hits_box() {
+        let (ray, inverse) = ray_along_x(0.5);
+        assert_eq!(
+            unit_box_at(0.0).hit(&ray, &inverse, f64::INFINITY),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn ray_misses_box() {
+        let (ray, inverse) = ray_along_x(2.0);
+        assert!(unit_box_at(0.0)
+            .hit(&ray, &inverse, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn box_beyond_t_max_is_missed() {
+        let (ray, inverse) = ray_along_x(0.5);
+        assert!(unit_box_at(0.0).hit(&ray, &inverse, 5.0).is_none());
+    }
+
+    #[test]
+    fn infinite_box_is_unbounded() {
+        assert!(!Aabb::infinite().is_bounded());
+        assert!(unit_box_at(0.0).is_bounded());
+    }
+
+    #[test]
+    fn every_item_ends_up_in_a_leaf() {
+        let items =
+            (0..100).map(|i| (i, unit_box_at(i as f64 * 2.0))).collect();
+        let bvh = Bvh::new(items);
+        let mut indices = bvh.indices.clone();
+        indices.sort();
+        assert_eq!(indices, (0..100).collect::<Vec<_>>());
+        assert!(bvh.nodes.len() > 1);
+        assert_eq!(bvh.bounds().max.x, 199.0);
+    }
+
+    #[test]
+    fn any_hit_stops_at_first_hit() {
+        let items =
+            (0..100).map(|i| (i, unit_box_at(i as f64 * 2.0))).collect();
+        let bvh = Bvh::new(items);
+        let (ray, _) = ray_along_x(0.5);
+        let mut tested = 0;
+        assert!(bvh.any_hit(&ray, f64::INFINITY, |_| {
+            tested += 1;
+            true
+        }));
+        assert_eq!(tested, 1);
+    }
+
+    #[test]
+    fn any_hit_skips_boxes_beyond_t_max() {
+        let items =
+            (0..100).map(|i| (i, unit_box_at(i as f64 * 2.0))).collect();
+        let bvh = Bvh::new(items);
+        let (ray, _) = ray_along_x(0.5);
+        let mut tested = vec![];
+        assert!(!bvh.any_hit(&ray, 15.0, |i| {
+            tested.push(i);
+            false
+        }));
+        // Only the boxes starting at x = 0, 2 and 4 are within 15 of the
+        // origin, though whole leaves get tested together
+        assert!(tested.contains(&0) && tested.contains(&2));
+        assert!(tested.iter().all(|&i| i < 2 * MAX_LEAF_SIZE));
+    }
+}
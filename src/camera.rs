@@ -1,8 +1,6 @@
-use crate::{
-    matrix_mul, LightSource, Matrix3x3, Point, Vector, Vector3D, IMG_HEIGHT,
-    IMG_SIZE, IMG_WIDTH,
-};
-use rayon::prelude::*;
+use crate::{matrix_mul, rotation_matrix, Matrix3x3, Point, Vector, Vector3D};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
 
 const APPROX_VUV: Vector3D = Vector {
     x: 0.0,
@@ -12,59 +10,122 @@ const APPROX_VUV: Vector3D = Vector {
 
 const DEFAULT_AMBIENT_COEFFICIENT: f64 = 0.3;
 
+pub const DEFAULT_IMG_WIDTH: usize = 1000;
+pub const DEFAULT_IMG_HEIGHT: usize = 1000;
+const DEFAULT_VIEW_DISTANCE: f64 = 1000.0;
+
+/// Closest the camera can get to the point it's looking at
+const MIN_TARGET_DISTANCE: f64 = 1.0;
+/// Furthest the camera can tilt up or down, in degrees. Any further and it
+/// would flip over when facing straight up or down
+const MAX_TILT: f64 = 89.0;
+
+#[derive(Clone)]
 pub struct Camera {
-    look_at: Point,
+    /// Where the camera is before being orbited around `look_at`
     view_reference_point: Point,
+    look_at: Point,
+    approx_view_up_vector: Vector3D,
+    /// Where the camera is after orbiting. This and the view vectors below
+    /// are worked out by `update_view` whenever the camera moves
+    position: Point,
     view_plane_normal: Vector3D,
     view_up_vector: Vector3D,
+    /// Points towards the left of the image, see `calc_pixel_point`
     view_right_vector: Vector3D,
     focal_length: f64,
-    screen: Vec<Vec<(Point, Vector3D)>>,
+    /// Where the screen is, which pixel rays are worked out from
+    screen_props: CameraProps,
     ambient_coefficient: f64,
     img_height: usize,
     img_width: usize,
     scale: f64,
-    pub light_source: LightSource,
     fov: f64,
     h_rotation: f64,
     v_rotation: f64,
 }
 
+/// Everything needed to set up a `Camera`. Scene files use the shorter names
+/// in brackets
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct CameraParams {
+    /// (`position`)
+    #[serde(rename = "position")]
     pub view_reference_point: Point,
+    /// Point in the centre of the view (`target`)
+    #[serde(rename = "target")]
+    pub look_at: Point,
+    /// (`up`)
+    #[serde(rename = "up")]
     pub approx_view_up_vector: Vector3D,
     pub focal_length: f64,
+    /// (`height`)
+    #[serde(rename = "height", deserialize_with = "image_size")]
     pub img_height: usize,
+    /// (`width`)
+    #[serde(rename = "width", deserialize_with = "image_size")]
     pub img_width: usize,
     pub scale: f64,
-    pub light_source: LightSource,
     pub fov: f64,
+    /// (`ambient`)
+    #[serde(rename = "ambient")]
     pub ambient_coefficient: f64,
+    /// Degrees the camera has been orbited around its target horizontally
+    pub h_rotation: f64,
+    /// Degrees the camera has been orbited around its target vertically
+    pub v_rotation: f64,
+}
+
+/// Images need at least one pixel each way to be rendered
+fn image_size<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<usize, D::Error> {
+    let size = usize::deserialize(deserializer)?;
+    if size > 0 {
+        Ok(size)
+    } else {
+        Err(D::Error::custom("image sizes must be at least 1 pixel"))
+    }
+}
+
+impl CameraParams {
+    /// Why a camera can't be made from these parameters, if it can't. The
+    /// camera has to be far enough from its target to tell which way to face
+    pub fn validate(&self) -> Result<(), String> {
+        let distance = (self.look_at - self.view_reference_point).magnitude();
+        if distance < MIN_TARGET_DISTANCE {
+            Err(format!(
+                "the camera's position must be at least \
+                 {MIN_TARGET_DISTANCE} from its target"
+            ))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Default for CameraParams {
     fn default() -> Self {
         Self {
-            view_reference_point: Point::new(
-                0.0,
-                0.0,
-                -(IMG_SIZE as f64) * 1.0,
-            ),
+            view_reference_point: Point::new(0.0, 0.0, -DEFAULT_VIEW_DISTANCE),
+            look_at: Point::new(0.0, 0.0, 0.0),
             approx_view_up_vector: APPROX_VUV,
             focal_length: 100.0,
-            img_height: IMG_HEIGHT as usize,
-            img_width: IMG_WIDTH as usize,
+            img_height: DEFAULT_IMG_HEIGHT,
+            img_width: DEFAULT_IMG_WIDTH,
             scale: 1.0,
-            light_source: LightSource::default(),
             fov: 45.0,
             ambient_coefficient: DEFAULT_AMBIENT_COEFFICIENT,
+            h_rotation: 0.0,
+            v_rotation: 0.0,
         }
     }
 }
 
 /// Just used to pass camera properties to associated functions during parallel
 /// iteration, to avoid headaches regarding immutable + mutable references to self
-#[derive(Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct CameraProps {
     pub screen_center_point: Point,
     pub img_width: usize,
@@ -81,45 +142,57 @@ pub struct CameraProps {
 }
 
 impl Camera {
-    /// Create a new `Camera` facing the origin (0, 0, 0)
+    /// Create a new `Camera` at `params.view_reference_point` facing
+    /// `params.look_at`, then orbit it around that point by the horizontal
+    /// and vertical rotation
     pub fn new(params: CameraParams) -> Self {
-        let look_at = Point::new(0.0, 0.0, 0.0);
-        let mut view_plane_normal = look_at - params.view_reference_point;
-        view_plane_normal.normalise();
-
-        let mut view_right_vector =
-            view_plane_normal * params.approx_view_up_vector;
-        view_right_vector.normalise();
-
-        let mut view_up_vector = view_right_vector * view_plane_normal;
-        view_up_vector.normalise();
-
         let mut camera = Self {
-            look_at,
             view_reference_point: params.view_reference_point,
-            view_plane_normal,
-            view_up_vector,
-            view_right_vector,
+            look_at: params.look_at,
+            approx_view_up_vector: params.approx_view_up_vector,
+            position: params.view_reference_point,
+            view_plane_normal: Vector3D::default(),
+            view_up_vector: Vector3D::default(),
+            view_right_vector: Vector3D::default(),
             focal_length: params.focal_length,
-            screen: vec![],
+            screen_props: CameraProps::default(),
             img_height: params.img_height,
             img_width: params.img_width,
             scale: params.scale,
-            light_source: LightSource::default(),
             fov: params.fov,
-            h_rotation: 0.0,
-            v_rotation: 0.0,
+            h_rotation: params.h_rotation,
+            v_rotation: params.v_rotation,
             ambient_coefficient: params.ambient_coefficient,
         };
-        camera.new_screen();
-        // println!("h: {}, v: {}", camera.h_rotation, camera.v_rotation);
-        // println!("BEFORE:\n{:#?}", camera.camera_props());
-
+        camera.update_view();
         camera
     }
 
+    /// The parameters which would recreate this camera as it is now
+    pub fn params(&self) -> CameraParams {
+        CameraParams {
+            view_reference_point: self.view_reference_point,
+            look_at: self.look_at,
+            approx_view_up_vector: self.approx_view_up_vector,
+            focal_length: self.focal_length,
+            img_height: self.img_height,
+            img_width: self.img_width,
+            scale: self.scale,
+            fov: self.fov,
+            ambient_coefficient: self.ambient_coefficient,
+            h_rotation: self.h_rotation,
+            v_rotation: self.v_rotation,
+        }
+    }
+
+    /// Where the camera is
     pub fn vrp(&self) -> Point {
-        self.general_rotation_matrix() * self.view_reference_point
+        self.position
+    }
+
+    /// The point in the centre of the view, which the camera orbits around
+    pub fn target(&self) -> Point {
+        self.look_at
     }
 
     pub fn vpn(&self) -> Vector3D {
@@ -139,40 +212,52 @@ impl Camera {
     }
 
     pub fn set_ambient_coefficient(&mut self, new_value: f64) {
-        let new_value = if new_value > 1.0 {
-            1.0
-        } else if new_value < 0.0 {
-            0.0
-        } else {
-            new_value
-        };
+        self.ambient_coefficient = new_value.clamp(0.0, 1.0);
+    }
+
+    /// Put the camera at `position` facing `target`, with `up` roughly
+    /// towards the top of the image, and forget any orbiting. Ignored if the
+    /// two points are too close together to tell which way to face
+    pub fn look_at(&mut self, position: Point, target: Point, up: Vector3D) {
+        if (target - position).magnitude() < MIN_TARGET_DISTANCE {
+            log::warn!("Camera can't look at {target:?} from {position:?}");
+            return;
+        }
+        self.view_reference_point = position;
+        self.look_at = target;
+        self.approx_view_up_vector = up;
+        self.h_rotation = 0.0;
+        self.v_rotation = 0.0;
+        self.update_view();
+    }
 
-        self.ambient_coefficient = new_value;
+    /// Move the camera to `position`, still facing its target
+    pub fn set_position(&mut self, position: Point) {
+        self.look_at(position, self.look_at, self.current_up());
     }
 
-    pub fn light_source(&self) -> LightSource {
-        self.light_source
+    /// Turn the camera to face `target` without moving it
+    pub fn set_target(&mut self, target: Point) {
+        self.look_at(self.position, target, self.current_up());
     }
 
     pub fn reset_vrp(&mut self) {
-        self.view_up_vector = APPROX_VUV;
         self.h_rotation = 0.0;
         self.v_rotation = 0.0;
-        self.adjust_view();
+        self.update_view();
     }
 
     pub fn reset_x(&mut self) {
         self.h_rotation = 0.0;
-        self.adjust_view();
+        self.update_view();
     }
 
     pub fn reset_y(&mut self) {
-        self.view_up_vector = APPROX_VUV;
         self.v_rotation = 0.0;
-        self.adjust_view();
+        self.update_view();
     }
 
-    /// Move camera along the x-axis
+    /// Orbit the camera horizontally around its target
     pub fn move_x(&mut self, degrees: f64) {
         self.h_rotation += degrees;
         if self.h_rotation > 360.0 {
@@ -180,18 +265,86 @@ impl Camera {
         } else if self.h_rotation < 0.0 {
             self.h_rotation += 360.0;
         }
-        self.adjust_view();
+        self.update_view();
     }
 
-    /// Move camera along the y-axis
+    /// Orbit the camera vertically around its target
     pub fn move_y(&mut self, degrees: f64) {
-        self.v_rotation += degrees;
-        if self.v_rotation > 90.0 {
-            self.v_rotation = 90.0;
-        } else if self.v_rotation < -90.0 {
-            self.v_rotation = -90.0;
-        }
-        self.adjust_view();
+        self.v_rotation = (self.v_rotation + degrees).clamp(-90.0, 90.0);
+        self.update_view();
+    }
+
+    /// Turn the camera `degrees` to the right without moving it, taking the
+    /// target with it
+    pub fn pan(&mut self, degrees: f64) {
+        let up = self.current_up();
+        let rotation = rotation_matrix(up, degrees);
+        let target = self.position + rotation * (self.look_at - self.position);
+        self.look_at(self.position, target, up);
+    }
+
+    /// Tip the camera `degrees` upwards without moving it, taking the target
+    /// with it. It stops short of facing straight up or down
+    pub fn tilt(&mut self, degrees: f64) {
+        let up = self.current_up();
+        let mut normalised_up = up;
+        normalised_up.normalise();
+        let elevation = 90.0
+            - self
+                .view_plane_normal
+                .dot(&normalised_up)
+                .clamp(-1.0, 1.0)
+                .acos()
+                .to_degrees();
+        let degrees =
+            (elevation + degrees).clamp(-MAX_TILT, MAX_TILT) - elevation;
+        // Turning around the left of the image tips the view upwards
+        let rotation = rotation_matrix(self.view_right_vector, degrees);
+        let target = self.position + rotation * (self.look_at - self.position);
+        self.look_at(self.position, target, up);
+    }
+
+    /// Move the camera `distance` towards its target, or away from it if
+    /// negative. It stops just short of the target
+    pub fn dolly(&mut self, distance: f64) {
+        let offset = self.view_reference_point - self.look_at;
+        let current = offset.magnitude();
+        let new = (current - distance).max(MIN_TARGET_DISTANCE);
+        self.view_reference_point = self.look_at + offset * (new / current);
+        self.update_view();
+    }
+
+    /// Slide the camera and its target to the right and up, keeping the
+    /// direction it faces
+    pub fn truck(&mut self, right: f64, up: f64) {
+        self.fly(0.0, right, up);
+    }
+
+    /// Move the camera and its target forwards, to the right and up relative
+    /// to the direction it faces, for flying through the scene
+    pub fn fly(&mut self, forward: f64, right: f64, up: f64) {
+        let offset = self.view_plane_normal * forward
+            + self.view_right_vector * -right
+            + self.view_up_vector * up;
+        self.view_reference_point = self.view_reference_point + offset;
+        self.look_at = self.look_at + offset;
+        self.update_view();
+    }
+
+    pub fn img_width(&self) -> usize {
+        self.img_width
+    }
+
+    pub fn img_height(&self) -> usize {
+        self.img_height
+    }
+
+    /// Change the size of the image the camera renders, keeping the field of
+    /// view across the wider of the two dimensions
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        self.img_width = width;
+        self.img_height = height;
+        self.setup_screen();
     }
 
     pub fn h_rotation(&self) -> f64 {
@@ -202,14 +355,15 @@ impl Camera {
         self.v_rotation
     }
 
-    pub fn pixel_props(
-        &self,
-        i: usize,
-        j: usize,
-        rotation_matrix: &Matrix3x3<f64>,
-    ) -> (Point, Vector3D) {
-        let (origin, direction) = self.screen[j as usize][i];
-        (rotation_matrix * origin, rotation_matrix * direction)
+    pub fn pixel_props(&self, i: usize, j: usize) -> (Point, Vector3D) {
+        self.sample_props(i as f64, j as f64)
+    }
+
+    /// Like `pixel_props`, but for a point anywhere on the screen rather than
+    /// just the centre of a pixel. `x` and `y` are measured in pixels, so
+    /// `(i as f64, j as f64)` gives the same ray as `pixel_props(i, j)`
+    pub fn sample_props(&self, x: f64, y: f64) -> (Point, Vector3D) {
+        Self::calc_pixel_props(x, y, &self.screen_props)
     }
 
     pub fn camera_props(&self) -> CameraProps {
@@ -237,50 +391,71 @@ impl Camera {
         }
     }
 
+    /// Rotation orbiting the camera around its target, horizontally around
+    /// its up vector and vertically around the sideways axis of the image
     pub fn general_rotation_matrix(&self) -> Matrix3x3<f64> {
+        let (_, view_right_vector, _) = Self::view_vectors(
+            self.look_at - self.view_reference_point,
+            self.approx_view_up_vector,
+        );
         matrix_mul(
-            self.horizontal_rotation_matrix(),
-            self.vertical_rotation_matrix(),
+            rotation_matrix(self.approx_view_up_vector, self.h_rotation),
+            rotation_matrix(view_right_vector * -1.0, self.v_rotation),
         )
     }
 
-    fn adjust_view(&mut self) {
-        let look_at = self.look_at;
-        let approx_view_up = self.view_up_vector;
-        self.view_plane_normal = look_at - self.vrp();
-        self.view_plane_normal.normalise();
-
-        self.view_right_vector = self.view_plane_normal * approx_view_up;
-        self.view_right_vector.normalise();
-
-        self.view_up_vector = self.view_right_vector * self.view_plane_normal;
-        self.view_up_vector.normalise();
+    /// The camera's up vector after orbiting
+    fn current_up(&self) -> Vector3D {
+        self.general_rotation_matrix() * self.approx_view_up_vector
+    }
+
+    /// Orbit the camera into place and set up the screen in front of it
+    fn update_view(&mut self) {
+        let rotation_matrix = self.general_rotation_matrix();
+        self.position = self.look_at
+            + rotation_matrix * (self.view_reference_point - self.look_at);
+        (
+            self.view_plane_normal,
+            self.view_right_vector,
+            self.view_up_vector,
+        ) = Self::view_vectors(
+            self.look_at - self.position,
+            rotation_matrix * self.approx_view_up_vector,
+        );
+        self.setup_screen();
     }
 
-    fn new_screen(&mut self) {
-        let mut empty_screen: Vec<Vec<(Point, Vector3D)>> =
-            Vec::with_capacity(self.img_height);
-        for _ in 0..empty_screen.capacity() {
-            empty_screen.push(Vec::with_capacity(self.img_width));
+    /// (view_plane_normal, view_right_vector, view_up_vector) for a camera
+    /// facing `forward`, with the up vector as close to `approx_up` as
+    /// possible
+    fn view_vectors(
+        forward: Vector3D,
+        approx_up: Vector3D,
+    ) -> (Vector3D, Vector3D, Vector3D) {
+        let mut view_plane_normal = forward;
+        view_plane_normal.normalise();
+
+        let mut view_right_vector = view_plane_normal * approx_up;
+        if view_right_vector.magnitude() < 1e-9 {
+            // Facing straight along the up vector, so any sideways direction
+            // will do
+            let sideways = if view_plane_normal.x.abs() < 0.9 {
+                Vector::new(1.0, 0.0, 0.0)
+            } else {
+                Vector::new(0.0, 0.0, 1.0)
+            };
+            view_right_vector = view_plane_normal * sideways;
         }
-        self.screen = empty_screen;
-        self.setup_screen();
+        view_right_vector.normalise();
+
+        let mut view_up_vector = view_right_vector * view_plane_normal;
+        view_up_vector.normalise();
+
+        (view_plane_normal, view_right_vector, view_up_vector)
     }
 
     fn setup_screen(&mut self) {
-        // This is just a way to get around the issue of passing an immutable
-        // reference to self into methods during par_iter_mut()
-        let camera_props = self.camera_props();
-
-        //TODO: speed this up by using arrays instead of vectors?
-        self.screen.par_iter_mut().enumerate().for_each(|(j, row)| {
-            row.clear();
-            let capacity = row.capacity();
-            for i in 0..capacity {
-                let pixel_props = Self::calc_pixel_props(i, j, &camera_props);
-                row.push(pixel_props);
-            }
-        });
+        self.screen_props = self.camera_props();
     }
 
     fn aspect_ratio(&self) -> f64 {
@@ -305,8 +480,8 @@ impl Camera {
     }
 
     fn calc_pixel_props(
-        i: usize,
-        j: usize,
+        i: f64,
+        j: f64,
         camera_props: &CameraProps,
     ) -> (Point, Vector3D) {
         let pixel_point = Self::calc_pixel_point(i, j, camera_props);
@@ -315,13 +490,7 @@ impl Camera {
         (pixel_point, pixel_direction)
     }
 
-    fn calc_pixel_point(
-        i: usize,
-        j: usize,
-        camera_props: &CameraProps,
-    ) -> Point {
-        let i = i as f64;
-        let j = j as f64;
+    fn calc_pixel_point(i: f64, j: f64, camera_props: &CameraProps) -> Point {
         let width = camera_props.img_width as f64;
         let height = camera_props.img_height as f64;
         let pixel_size = camera_props.pixel_size;
@@ -345,24 +514,6 @@ impl Camera {
         direction.normalise();
         direction
     }
-
-    fn vertical_rotation_matrix(&self) -> Matrix3x3<f64> {
-        let degrees = self.v_rotation.to_radians();
-        [
-            Vector::new(1.0, 0.0, 0.0),
-            Vector::new(0.0, degrees.cos(), degrees.sin()),
-            Vector::new(0.0, -degrees.sin(), degrees.cos()),
-        ]
-    }
-
-    fn horizontal_rotation_matrix(&self) -> Matrix3x3<f64> {
-        let degrees = self.h_rotation.to_radians();
-        [
-            Vector::new(degrees.cos(), 0.0, -degrees.sin()),
-            Vector::new(0.0, 1.0, 0.0),
-            Vector::new(degrees.sin(), 0.0, degrees.cos()),
-        ]
-    }
 }
 
 impl Default for Camera {
@@ -386,17 +537,156 @@ mod tests {
             img_height: IMG_HEIGHT as usize,
             img_width: IMG_WIDTH as usize,
             scale: PIXEL_SCALE,
-            light_source: LightSource::default(),
             fov: 45.0,
             ambient_coefficient: DEFAULT_AMBIENT_COEFFICIENT,
+            ..CameraParams::default()
         };
         Camera::new(camera_params)
     }
 
+    #[test]
+    fn sample_at_pixel_centre_matches_pixel() {
+        let camera = test_camera();
+        assert_eq!(camera.sample_props(12.0, 34.0), camera.pixel_props(12, 34));
+    }
+
+    #[test]
+    fn changing_resolution_keeps_view_centred() {
+        let mut camera = test_camera();
+        let (_, centre) = camera.pixel_props(500, 500);
+        let (_, left) = camera.pixel_props(0, 500);
+
+        camera.set_resolution(1920, 1080);
+        assert_eq!((camera.img_width(), camera.img_height()), (1920, 1080));
+        let (_, new_centre) = camera.pixel_props(960, 540);
+        let (_, new_left) = camera.pixel_props(0, 540);
+        assert!((new_centre - centre).magnitude() < 1e-9);
+        // The field of view stays across the width of the image
+        assert!((new_left - left).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn params_recreate_rotated_camera() {
+        let mut camera = test_camera();
+        camera.move_x(30.0);
+        camera.move_y(-20.0);
+        let copy = Camera::new(camera.params());
+        assert_eq!(
+            copy.general_rotation_matrix(),
+            camera.general_rotation_matrix()
+        );
+        assert_eq!(copy.pixel_props(10, 20), camera.pixel_props(10, 20));
+    }
+
     #[test]
     fn horizontal_rotation() {
         let mut camera = test_camera();
         camera.move_x(10.0);
         assert_eq!(0.0, camera.vrp().y);
     }
+
+    /// Direction of the ray through the centre of the image
+    fn centre_direction(camera: &Camera) -> Vector3D {
+        camera
+            .pixel_props(IMG_WIDTH as usize / 2, IMG_HEIGHT as usize / 2)
+            .1
+    }
+
+    fn direction(from: Point, to: Point) -> Vector3D {
+        let mut direction = to - from;
+        direction.normalise();
+        direction
+    }
+
+    fn assert_close(a: Vector3D, b: Vector3D) {
+        assert!((a - b).magnitude() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn faces_target() {
+        let target = Point::new(100.0, -50.0, 300.0);
+        let camera = Camera::new(CameraParams {
+            view_reference_point: Point::new(400.0, 200.0, -500.0),
+            look_at: target,
+            ..CameraParams::default()
+        });
+        assert_close(
+            centre_direction(&camera),
+            direction(camera.vrp(), target),
+        );
+    }
+
+    #[test]
+    fn orbits_around_target() {
+        let target = Point::new(100.0, -50.0, 300.0);
+        let mut camera = Camera::new(CameraParams {
+            look_at: target,
+            ..CameraParams::default()
+        });
+        let distance = (camera.vrp() - target).magnitude();
+        camera.move_x(40.0);
+        camera.move_y(-25.0);
+        assert!(((camera.vrp() - target).magnitude() - distance).abs() < 1e-9);
+        assert_close(
+            centre_direction(&camera),
+            direction(camera.vrp(), target),
+        );
+    }
+
+    #[test]
+    fn pan_and_tilt_turn_without_moving() {
+        let mut camera = test_camera();
+        camera.move_y(10.0);
+        let position = camera.vrp();
+        let (_, right) = camera.pixel_props(IMG_WIDTH as usize - 1, 500);
+        let before = centre_direction(&camera);
+        camera.pan(10.0);
+        assert_close(camera.vrp(), position);
+        // The view turns towards what was on the right of the image
+        let after = centre_direction(&camera);
+        assert!((after.dot(&before).acos().to_degrees() - 10.0).abs() < 1e-9);
+        assert!(after.dot(&right) > before.dot(&right));
+
+        let up = camera.vuv();
+        let before = centre_direction(&camera);
+        camera.tilt(10.0);
+        assert_close(camera.vrp(), position);
+        assert!(centre_direction(&camera).dot(&up) > before.dot(&up));
+    }
+
+    #[test]
+    fn tilt_stops_before_vertical() {
+        let mut camera = test_camera();
+        camera.tilt(200.0);
+        let elevation = centre_direction(&camera).dot(&APPROX_VUV).asin();
+        assert!((elevation.to_degrees() - MAX_TILT).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dolly_stops_short_of_target() {
+        let mut camera = test_camera();
+        camera.dolly(400.0);
+        assert!((camera.vrp().magnitude() - 600.0).abs() < 1e-9);
+        camera.dolly(5000.0);
+        assert!((camera.vrp().magnitude() - MIN_TARGET_DISTANCE).abs() < 1e-9);
+        camera.dolly(-99.0);
+        assert!((camera.vrp().magnitude() - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn truck_and_fly_move_target_too() {
+        let mut camera = test_camera();
+        let before = centre_direction(&camera);
+        let position = camera.vrp();
+        let (_, right) = camera.pixel_props(IMG_WIDTH as usize - 1, 500);
+        camera.truck(50.0, 20.0);
+        assert_close(centre_direction(&camera), before);
+        assert!((camera.vrp() - position).dot(&right) > 0.0);
+        assert!(camera.target().x > 0.0 && camera.target().y > 0.0);
+
+        let target = camera.target();
+        camera.fly(100.0, 0.0, 0.0);
+        assert_close(camera.target() - target, before * 100.0);
+        assert_close(centre_direction(&camera), before);
+    }
 }
@@ -0,0 +1,300 @@
+use env_logger::Builder;
+use ray_tracing::{
+    render_with_progress, set_global_rayon_threads, timeit, write_img, Camera,
+    HdrImage, Integrator, ReconstructionFilter, SamplePattern, Scene,
+    ToneMapOperator, ToneMapping,
+};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+const USAGE: &str = "\
+Render a scene without opening a window
+
+Usage: ray-tracer-cli [OPTIONS]
+
+Options:
+      --scene <PATH>       Scene file to render [default: the demo scene]
+  -o, --output <PATH>      Where to write the PNG [default: render.png]
+      --width <PIXELS>     Width of the image [default: from the scene]
+      --height <PIXELS>    Height of the image [default: from the scene]
+  -s, --samples <N>        Rays traced per pixel [default: from the scene]
+      --sampler <NAME>     random, stratified, jittered, halton or sobol
+                           [default: from the scene]
+      --filter <NAME>      box, tent, gaussian or mitchell
+                           [default: from the scene]
+      --integrator <NAME>  phong or path_tracer [default: from the scene]
+  -t, --threads <N>        Threads to render with [default: one per core]
+      --tone-map <NAME>    clamp, reinhard or aces [default: clamp]
+      --exposure <STOPS>   Exposure adjustment [default: 0]
+      --linear             Write linear values rather than encoding them
+                           with the sRGB curve
+      --save-scene <PATH>  Write the scene to a scene file instead of
+                           rendering it, e.g. to start a new scene from the
+                           demo one
+  -q, --quiet              Don't report progress
+  -h, --help               Print this message";
+
+pub fn main() -> ExitCode {
+    setup_logging();
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    if let Some(threads) = args.threads {
+        set_global_rayon_threads(threads);
+    }
+
+    let mut scene = match &args.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("error: couldn't load {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Scene::demo(Camera::default()),
+    };
+    if args.width.is_some() || args.height.is_some() {
+        let camera = &mut scene.camera;
+        let width = args.width.unwrap_or(camera.img_width());
+        let height = args.height.unwrap_or(camera.img_height());
+        camera.set_resolution(width, height);
+    }
+    if let Some(samples) = args.samples {
+        scene.settings.samples_per_pixel = samples;
+    }
+    if let Some(sample_pattern) = args.sample_pattern {
+        scene.settings.sample_pattern = sample_pattern;
+    }
+    if let Some(filter) = args.filter {
+        scene.settings.filter = filter;
+    }
+    if let Some(integrator) = args.integrator {
+        scene.settings.integrator = integrator;
+    }
+
+    if let Some(path) = &args.save_scene {
+        return match scene.save(path) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: couldn't save {}: {e}", path.display());
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut img = HdrImage::new(0, 0);
+    let last_percent = Mutex::new(0);
+    let render_time = timeit!({
+        render_with_progress(&mut img, &scene, |done, total| {
+            if !args.quiet {
+                report_progress(done, total, &last_percent);
+            }
+        });
+    });
+    if !args.quiet {
+        eprintln!();
+    }
+    log::info!("Render time: {}ms", render_time.as_millis());
+
+    match write_img(&img, &args.tone_mapping, &args.output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: couldn't write {}: {e}", args.output.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn setup_logging() {
+    let mut log_builder = Builder::new();
+    log_builder.filter_level(log::LevelFilter::Warn);
+    log_builder.parse_env("LOG");
+    log_builder.init();
+}
+
+/// Print how far through the render is, overwriting the previous report.
+/// `last_percent` stops reports from threads which finished a row slightly
+/// out of order from going backwards or flooding the terminal
+fn report_progress(done: usize, total: usize, last_percent: &Mutex<usize>) {
+    let percent = done * 100 / total.max(1);
+    let mut last_percent = last_percent.lock().unwrap();
+    if percent > *last_percent {
+        *last_percent = percent;
+        eprint!("\rRendering... {percent:>3}%");
+        std::io::stderr().flush().ok();
+    }
+}
+
+/// Settings which are `None` weren't given, and come from the scene instead
+#[derive(Debug, PartialEq)]
+struct Args {
+    scene: Option<PathBuf>,
+    output: PathBuf,
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<usize>,
+    sample_pattern: Option<SamplePattern>,
+    filter: Option<ReconstructionFilter>,
+    integrator: Option<Integrator>,
+    threads: Option<usize>,
+    tone_mapping: ToneMapping,
+    save_scene: Option<PathBuf>,
+    quiet: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            output: PathBuf::from("render.png"),
+            width: None,
+            height: None,
+            samples: None,
+            sample_pattern: None,
+            filter: None,
+            integrator: None,
+            threads: None,
+            tone_mapping: ToneMapping::default(),
+            save_scene: None,
+            quiet: false,
+        }
+    }
+}
+
+impl Args {
+    /// Parse the command line arguments, not including the program name.
+    /// Returns `None` if help was asked for
+    fn parse(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Option<Self>, String> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            let mut value =
+                || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--scene" => parsed.scene = Some(PathBuf::from(value()?)),
+                "-o" | "--output" => parsed.output = PathBuf::from(value()?),
+                "--width" => parsed.width = Some(positive(&arg, &value()?)?),
+                "--height" => parsed.height = Some(positive(&arg, &value()?)?),
+                "-s" | "--samples" => {
+                    parsed.samples = Some(positive(&arg, &value()?)?)
+                }
+                "--sampler" => {
+                    parsed.sample_pattern =
+                        Some(SamplePattern::from_str(&value()?)?)
+                }
+                "--filter" => {
+                    parsed.filter =
+                        Some(ReconstructionFilter::from_str(&value()?)?)
+                }
+                "--integrator" => {
+                    parsed.integrator = Some(Integrator::from_str(&value()?)?)
+                }
+                "-t" | "--threads" => {
+                    parsed.threads = Some(positive(&arg, &value()?)?)
+                }
+                "--tone-map" => {
+                    parsed.tone_mapping.operator =
+                        ToneMapOperator::from_str(&value()?)?
+                }
+                "--exposure" => {
+                    parsed.tone_mapping.exposure = value()?
+                        .parse()
+                        .map_err(|_| format!("{arg} needs a number"))?
+                }
+                "--linear" => parsed.tone_mapping.srgb = false,
+                "--save-scene" => {
+                    parsed.save_scene = Some(PathBuf::from(value()?))
+                }
+                "-q" | "--quiet" => parsed.quiet = true,
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+        Ok(Some(parsed))
+    }
+}
+
+fn positive(arg: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{arg} needs a whole number above 0")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Args>, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_uses_defaults() {
+        assert_eq!(parse(&[]), Ok(Some(Args::default())));
+    }
+
+    #[test]
+    fn parses_flags() {
+        let args = parse(&[
+            "--scene",
+            "scene.toml",
+            "-o",
+            "out.png",
+            "--width",
+            "640",
+            "--height",
+            "480",
+            "-s",
+            "4",
+            "--sampler",
+            "sobol",
+            "--filter",
+            "mitchell",
+            "--integrator",
+            "path_tracer",
+            "-t",
+            "2",
+            "--tone-map",
+            "aces",
+            "--linear",
+            "-q",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(args.output, PathBuf::from("out.png"));
+        assert_eq!(args.scene, Some(PathBuf::from("scene.toml")));
+        assert_eq!((args.width, args.height), (Some(640), Some(480)));
+        assert_eq!(args.samples, Some(4));
+        assert_eq!(args.sample_pattern, Some(SamplePattern::Sobol));
+        assert_eq!(args.filter, Some(ReconstructionFilter::Mitchell));
+        assert_eq!(args.integrator, Some(Integrator::PathTracer));
+        assert_eq!(args.threads, Some(2));
+        assert_eq!(args.tone_mapping.operator, ToneMapOperator::Aces);
+        assert!(!args.tone_mapping.srgb);
+        assert!(args.quiet);
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(parse(&["--width", "0"]).is_err());
+        assert!(parse(&["--samples"]).is_err());
+        assert!(parse(&["--tone-map", "sepia"]).is_err());
+        assert!(parse(&["--filter", "lanczos"]).is_err());
+        assert!(parse(&["--integrator", "photon_map"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+use crate::{LightColour, Vector3D};
+use image::codecs::hdr::HdrDecoder;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{DynamicImage, ImageError, ImageResult, Rgb32FImage};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::f64::consts::{PI, TAU};
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Light arriving from far away in every direction, from an equirectangular
+/// (latitude-longitude) image wrapped around the scene. Rays which miss every
+/// shape see it as the background, and the path tracer is lit by it. Clones
+/// share the same pixels
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentMap {
+    /// The image file, usually a Radiance `.hdr` so the sky can be far
+    /// brighter than 1.0. In scene files, it's relative to the scene file
+    path: PathBuf,
+    /// Multiplies the brightness of the whole image
+    #[serde(default = "default_intensity", deserialize_with = "intensity")]
+    intensity: f64,
+    /// Degrees the image is turned around the y axis. Unturned, the middle
+    /// of the image is in the +z direction
+    #[serde(default)]
+    rotation: f64,
+    /// Black until the image is loaded, like having no environment map
+    #[serde(skip)]
+    image: Arc<EnvironmentImage>,
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+/// Negative intensities are rejected rather than clamped like
+/// `with_intensity` does, so mistakes in scene files aren't hidden
+fn intensity<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    let intensity = f64::deserialize(deserializer)?;
+    if intensity >= 0.0 {
+        Ok(intensity)
+    } else {
+        Err(D::Error::custom(format!(
+            "intensity can't be negative, not {intensity}"
+        )))
+    }
+}
+
+/// A direction towards the environment picked by `EnvironmentMap::sample`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnvironmentSample {
+    /// Unit vector towards the environment
+    pub direction: Vector3D,
+    /// Light arriving from `direction`
+    pub radiance: LightColour,
+    /// Probability density of picking `direction`, per unit solid angle
+    pub pdf: f64,
+}
+
+/// Pixels of an environment map, along with running totals of how much
+/// light each one gives for picking them in proportion to it
+struct EnvironmentImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<LightColour>,
+    /// Running totals of the rows' shares of the light, from 0.0 to 1.0,
+    /// with one more entry than there are rows
+    rows: Vec<f64>,
+    /// Running totals of each pixel's share of the light in its row
+    columns: Vec<Vec<f64>>,
+    /// Sum of every pixel's weight, 0.0 if the image is black
+    total: f64,
+}
+
+impl EnvironmentImage {
+    fn new(width: usize, height: usize, pixels: Vec<LightColour>) -> Self {
+        let mut rows = vec![0.0];
+        let mut columns = Vec::with_capacity(height);
+        for j in 0..height {
+            let mut row = vec![0.0];
+            for i in 0..width {
+                let total = row[i] + weight(pixels[j * width + i], j, height);
+                row.push(total);
+            }
+            rows.push(rows[j] + row[width]);
+            columns.push(normalised(row));
+        }
+        let total = rows[height];
+        Self {
+            width,
+            height,
+            pixels,
+            rows: normalised(rows),
+            columns,
+            total,
+        }
+    }
+
+    fn pixel_index(&self, (u, v): (f64, f64)) -> (usize, usize) {
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        (i, j)
+    }
+}
+
+impl Default for EnvironmentImage {
+    fn default() -> Self {
+        Self::new(1, 1, vec![LightColour::default()])
+    }
+}
+
+impl Debug for EnvironmentImage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnvironmentImage")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<&DynamicImage> for EnvironmentImage {
+    /// HDR images keep their values above 1.0. Others are scaled to 0.0 to
+    /// 1.0
+    fn from(image: &DynamicImage) -> Self {
+        let image = image.to_rgb32f();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                LightColour::new(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64)
+            })
+            .collect();
+        Self::new(image.width() as usize, image.height() as usize, pixels)
+    }
+}
+
+impl EnvironmentMap {
+    /// An environment map using the image at `path`, which isn't read until
+    /// `load` is called
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            intensity: default_intensity(),
+            rotation: 0.0,
+            image: Arc::default(),
+        }
+    }
+
+    /// Use `image` for the environment rather than reading its file. Empty
+    /// images are ignored, leaving the environment as it was
+    pub fn with_image(mut self, image: &DynamicImage) -> Self {
+        if !is_empty(image) {
+            self.image = Arc::new(EnvironmentImage::from(image));
+        }
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity.max(0.0);
+        self
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    /// Read the image file, relative to `base_dir`. Images with no pixels
+    /// are rejected
+    pub fn load(&mut self, base_dir: &Path) -> ImageResult<()> {
+        let path = base_dir.join(&self.path);
+        let is_hdr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        let image = if is_hdr {
+            read_hdr(&path)?
+        } else {
+            image::open(path)?
+        };
+        if is_empty(&image) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(
+                    "environment maps need at least one pixel".to_string(),
+                ),
+            )));
+        }
+        self.image = Arc::new(EnvironmentImage::from(&image));
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Change where the image file is, without reloading it
+    pub fn set_path(&mut self, path: impl Into<PathBuf>) {
+        self.path = path.into();
+    }
+
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    /// Light arriving from `direction`, which needn't be a unit vector
+    pub fn radiance(&self, direction: &Vector3D) -> LightColour {
+        let image = &self.image;
+        let (i, j) = image.pixel_index(self.uv(direction));
+        image.pixels[j * image.width + i] * self.intensity
+    }
+
+    /// Pick a direction in proportion to how much light arrives from it,
+    /// given two random numbers from 0.0 to 1.0. `None` if the environment
+    /// is black
+    pub fn sample(&self, u: (f64, f64)) -> Option<EnvironmentSample> {
+        let image = &self.image;
+        if image.total <= 0.0 {
+            return None;
+        }
+        let (row, v) = pick(&image.rows, u.1);
+        let (_, u) = pick(&image.columns[row], u.0);
+        let direction = self.direction((u, v));
+        let pdf = self.pdf(&direction);
+        (pdf > 0.0).then(|| EnvironmentSample {
+            direction,
+            radiance: self.radiance(&direction),
+            pdf,
+        })
+    }
+
+    /// Probability density of `sample` picking `direction`
+    pub fn pdf(&self, direction: &Vector3D) -> f64 {
+        let image = &self.image;
+        let mut direction = *direction;
+        direction.normalise();
+        let sin = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+        if image.total <= 0.0 || sin <= 0.0 {
+            return 0.0;
+        }
+        let (i, j) = image.pixel_index(self.uv(&direction));
+        let pixel = weight(image.pixels[j * image.width + i], j, image.height);
+        // Spread evenly over the pixel's share of the image, which covers
+        // 2π by π radians of longitude and latitude
+        let pixels = (image.width * image.height) as f64;
+        pixel / image.total * pixels / (TAU * PI * sin)
+    }
+
+    /// Position in the image, from 0.0 to 1.0 across and down, of the light
+    /// arriving from `direction`
+    fn uv(&self, direction: &Vector3D) -> (f64, f64) {
+        let mut d = *direction;
+        d.normalise();
+        let u = 0.5 + d.x.atan2(d.z) / TAU - self.rotation / 360.0;
+        (u.rem_euclid(1.0), d.y.clamp(-1.0, 1.0).acos() / PI)
+    }
+
+    /// Unit vector towards the light at `(u, v)` in the image
+    fn direction(&self, (u, v): (f64, f64)) -> Vector3D {
+        let phi = (u - 0.5 + self.rotation / 360.0) * TAU;
+        let theta = v * PI;
+        Vector3D::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl PartialEq for EnvironmentMap {
+    /// Environment maps are compared by their settings, not their pixels
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.intensity == other.intensity
+            && self.rotation == other.rotation
+    }
+}
+
+/// Read a Radiance `.hdr` file. `image::open` squashes them down to 8 bits
+/// per channel, losing everything brighter than 1.0
+fn read_hdr(path: &Path) -> ImageResult<DynamicImage> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let samples = pixels.into_iter().flat_map(|pixel| pixel.0).collect();
+    Rgb32FImage::from_raw(metadata.width, metadata.height, samples)
+        .map(DynamicImage::from)
+        .ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))
+        })
+}
+
+fn is_empty(image: &DynamicImage) -> bool {
+    image.width() == 0 || image.height() == 0
+}
+
+/// How often a pixel in row `j` of an image `height` pixels high is picked,
+/// relative to the others. The rows near the poles are squashed together in
+/// equirectangular images, so they're picked less
+fn weight(colour: LightColour, j: usize, height: usize) -> f64 {
+    let brightness = (colour.x + colour.y + colour.z) / 3.0;
+    let sin = (PI * (j as f64 + 0.5) / height as f64).sin();
+    brightness.max(0.0) * sin
+}
+
+/// Running totals scaled so the last is 1.0, or left as they are if they're
+/// all 0.0
+fn normalised(mut totals: Vec<f64>) -> Vec<f64> {
+    let last = totals[totals.len() - 1];
+    if last > 0.0 {
+        totals.iter_mut().for_each(|total| *total /= last);
+    }
+    totals
+}
+
+/// Index of the entry `u` falls in given running totals from 0.0 to 1.0, and
+/// how far along all of them it is, spread evenly within the entry
+fn pick(totals: &[f64], u: f64) -> (usize, f64) {
+    let n = totals.len() - 1;
+    let i = totals.partition_point(|&total| total <= u).clamp(1, n) - 1;
+    let width = totals[i + 1] - totals[i];
+    let offset = if width > 0.0 {
+        ((u - totals[i]) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (i, (i as f64 + offset) / n as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb32FImage;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn uniform(radiance: f32) -> EnvironmentMap {
+        let image = Rgb32FImage::from_pixel(8, 4, image::Rgb([radiance; 3]));
+        EnvironmentMap::new("").with_image(&image.into())
+    }
+
+    #[test]
+    fn directions_match_image_positions() {
+        let environment = uniform(1.0).with_rotation(30.0);
+        for uv in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let (u, v) = environment.uv(&environment.direction(uv));
+            assert!((u - uv.0).abs() < 1e-9 && (v - uv.1).abs() < 1e-9);
+        }
+        let middle = uniform(1.0).direction((0.5, 0.5));
+        assert!((middle - Vector3D::new(0.0, 0.0, 1.0)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        // Mean of 1 / pdf over samples drawn from it is the area of the
+        // sphere, 4π
+        let mut image = Rgb32FImage::from_pixel(16, 8, image::Rgb([0.05; 3]));
+        image.put_pixel(3, 2, image::Rgb([20.0, 10.0, 5.0]));
+        image.put_pixel(10, 5, image::Rgb([0.0; 3]));
+        let environment = EnvironmentMap::new("").with_image(&image.into());
+        let n = 20000;
+        let mut area = 0.0;
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..n {
+            let sample = environment.sample((rng.gen(), rng.gen())).unwrap();
+            assert_eq!(sample.pdf, environment.pdf(&sample.direction));
+            assert_eq!(
+                sample.radiance,
+                environment.radiance(&sample.direction)
+            );
+            area += 1.0 / sample.pdf / n as f64;
+        }
+        let sphere = 4.0 * PI;
+        assert!((area - sphere).abs() < sphere * 0.05, "{area}");
+    }
+
+    #[test]
+    fn bright_pixels_are_picked_most() {
+        let mut image = Rgb32FImage::new(16, 8);
+        image.put_pixel(12, 3, image::Rgb([100.0; 3]));
+        image.put_pixel(4, 4, image::Rgb([0.01; 3]));
+        let environment = EnvironmentMap::new("").with_image(&image.into());
+        let sample = environment.sample((0.5, 0.5)).unwrap();
+        assert_eq!(sample.radiance, LightColour::new(100.0, 100.0, 100.0));
+    }
+
+    #[test]
+    fn black_environment_has_nothing_to_sample() {
+        let environment = EnvironmentMap::new("missing.hdr");
+        assert!(environment.sample((0.5, 0.5)).is_none());
+        assert_eq!(environment.pdf(&Vector3D::new(0.0, 0.0, 1.0)), 0.0);
+        assert_eq!(
+            environment.radiance(&Vector3D::new(1.0, 0.0, 0.0)),
+            LightColour::default()
+        );
+    }
+
+    #[test]
+    fn empty_images_are_refused() {
+        let empty = Rgb32FImage::new(0, 0).into();
+        let environment = uniform(0.5).with_image(&empty);
+        let radiance = environment.radiance(&Vector3D::new(1.0, 0.0, 0.0));
+        assert_eq!(radiance, LightColour::new(0.5, 0.5, 0.5));
+
+        let dir = std::env::temp_dir().join("ray-tracing-environment-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("empty.hdr"),
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 0\n",
+        )
+        .unwrap();
+        assert!(EnvironmentMap::new("empty.hdr").load(&dir).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{LightColour, PixelColour, ToneMapping, BYTES_PER_PIXEL};
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Image which keeps the linear radiance reaching each pixel as floating
+/// point, so highlights brighter than 1.0 aren't clipped or wrapped until it's
+/// quantised for display or export
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<LightColour>,
+}
+
+impl HdrImage {
+    /// Create a black image
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![LightColour::default(); width * height],
+        }
+    }
+
+    /// Change the size of the image. Any existing pixels are thrown away
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Self::new(width, height);
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, i: usize, j: usize) -> LightColour {
+        self.pixels[j * self.width + i]
+    }
+
+    pub fn set_pixel(&mut self, i: usize, j: usize, colour: LightColour) {
+        self.pixels[j * self.width + i] = colour;
+    }
+
+    pub fn pixels(&self) -> &[LightColour] {
+        &self.pixels
+    }
+
+    /// Rows of the image, top to bottom, which can be filled in parallel.
+    /// Empty images have none
+    pub fn par_rows_mut(
+        &mut self,
+    ) -> impl IndexedParallelIterator<Item = &mut [LightColour]> {
+        self.pixels.par_chunks_mut(self.width.max(1))
+    }
+
+    /// Tone map and quantise into 8 bit RGBA rows `row_stride` bytes apart,
+    /// e.g. the pixel data of a `Pixbuf`
+    pub fn quantise_into(
+        &self,
+        bytes: &mut [u8],
+        row_stride: usize,
+        tone_mapping: &ToneMapping,
+    ) {
+        bytes
+            .par_chunks_mut(row_stride.max(1))
+            .zip(self.pixels.par_chunks(self.width.max(1)))
+            .for_each(|(row, colours)| {
+                row.chunks_exact_mut(BYTES_PER_PIXEL).zip(colours).for_each(
+                    |(px, colour)| {
+                        let colour = PixelColour::from_display_colour(
+                            &tone_mapping.apply(colour),
+                        );
+                        px.copy_from_slice(&[
+                            colour.x, colour.y, colour.z, 255,
+                        ]);
+                    },
+                );
+            });
+    }
+
+    /// Tone map and quantise into an 8 bit image which can be written to disk
+    pub fn to_rgba_image(&self, tone_mapping: &ToneMapping) -> RgbaImage {
+        let mut img = RgbaImage::new(self.width as u32, self.height as u32);
+        let row_stride = self.width * BYTES_PER_PIXEL;
+        self.quantise_into(&mut img, row_stride, tone_mapping);
+        img
+    }
+}
+
+/// Filter weighted total of the samples taken for a pixel
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PixelSamples {
+    pub total: LightColour,
+    pub weight: f64,
+}
+
+impl PixelSamples {
+    /// The weighted average of the samples, or black if there aren't any.
+    /// Negative filter lobes can overshoot below zero next to bright edges,
+    /// so that's clipped
+    pub fn colour(&self) -> LightColour {
+        if self.weight <= 0.0 {
+            return LightColour::default();
+        }
+        let colour = self.total / self.weight;
+        LightColour::new(
+            colour.x.max(0.0),
+            colour.y.max(0.0),
+            colour.z.max(0.0),
+        )
+    }
+}
+
+impl std::ops::Add for PixelSamples {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            total: self.total + rhs.total,
+            weight: self.weight + rhs.weight,
+        }
+    }
+}
+
+/// Samples for each pixel summed over several passes, so an image can keep
+/// being refined for as long as there's time to spare
+#[derive(Clone, Debug)]
+pub struct AccumulationBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<PixelSamples>,
+    passes: usize,
+}
+
+impl AccumulationBuffer {
+    /// Create a buffer with no samples in it
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![PixelSamples::default(); width * height],
+            passes: 0,
+        }
+    }
+
+    /// Change the size of the buffer, throwing away all the samples
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Self::new(width, height);
+    }
+
+    /// Throw away all the samples, e.g. because the scene has changed
+    pub fn clear(&mut self) {
+        self.pixels.fill(PixelSamples::default());
+        self.passes = 0;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// How many passes have been added since the buffer was last cleared
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+
+    /// Add the samples `pixel_samples` takes for each pixel, given its column
+    /// and row. Pixels are sampled in parallel
+    pub fn add_pass(
+        &mut self,
+        pixel_samples: impl Fn(usize, usize) -> PixelSamples + Sync,
+    ) {
+        self.pixels
+            .par_chunks_mut(self.width.max(1))
+            .enumerate()
+            .for_each(|(j, row)| {
+                row.iter_mut().enumerate().for_each(|(i, px)| {
+                    *px = *px + pixel_samples(i, j);
+                });
+            });
+        self.passes += 1;
+    }
+
+    /// Average the samples so far into `img`, resizing it if it doesn't
+    /// match
+    pub fn resolve_into(&self, img: &mut HdrImage) {
+        if img.width() != self.width || img.height() != self.height {
+            img.resize(self.width, self.height);
+        }
+        img.par_rows_mut()
+            .zip(self.pixels.par_chunks(self.width.max(1)))
+            .for_each(|(row, samples)| {
+                row.iter_mut()
+                    .zip(samples)
+                    .for_each(|(px, samples)| *px = samples.colour());
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bright_pixels_survive_until_quantised() {
+        let mut hdr = HdrImage::new(2, 1);
+        hdr.set_pixel(1, 0, LightColour::new(4.0, 0.5, -1.0));
+        assert_eq!(hdr.pixel(1, 0).x, 4.0);
+
+        let linear = ToneMapping {
+            srgb: false,
+            ..ToneMapping::default()
+        };
+        let img = hdr.to_rgba_image(&linear);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [255, 128, 0, 255]);
+    }
+
+    #[test]
+    fn passes_accumulate_into_weighted_average() {
+        let mut accumulation = AccumulationBuffer::new(2, 1);
+        let samples = |c: f64, weight: f64| PixelSamples {
+            total: LightColour::new(c, c, c) * weight,
+            weight,
+        };
+        accumulation.add_pass(|i, _| samples(i as f64, 1.0));
+        accumulation.add_pass(|_, _| samples(4.0, 3.0));
+        assert_eq!(accumulation.passes(), 2);
+
+        let mut hdr = HdrImage::new(0, 0);
+        accumulation.resolve_into(&mut hdr);
+        assert_eq!(hdr.pixel(0, 0), LightColour::new(3.0, 3.0, 3.0));
+        assert_eq!(hdr.pixel(1, 0), LightColour::new(3.25, 3.25, 3.25));
+
+        accumulation.clear();
+        accumulation.resolve_into(&mut hdr);
+        assert_eq!((accumulation.passes(), hdr.pixel(1, 0).x), (0, 0.0));
+    }
+
+    #[test]
+    fn empty_images_have_no_rows() {
+        let mut hdr = HdrImage::new(0, 0);
+        assert_eq!(hdr.par_rows_mut().count(), 0);
+        let mut accumulation = AccumulationBuffer::new(0, 5);
+        accumulation.add_pass(|_, _| PixelSamples::default());
+        accumulation.resolve_into(&mut hdr);
+        assert_eq!(hdr.to_rgba_image(&ToneMapping::default()).width(), 0);
+    }
+}
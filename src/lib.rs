@@ -1,24 +1,48 @@
+mod bsdf;
+mod bvh;
 mod camera;
+mod environment;
+mod framebuffer;
 mod lighting;
 mod material;
+mod mesh;
+mod path_tracer;
+#[cfg(feature = "gtk")]
+mod pixbuf;
+mod procedural;
 mod render;
+mod sampling;
+mod scene;
+mod scene_file;
 mod shapes;
+mod texture;
+mod tone_mapping;
 mod vector;
 
+pub use bsdf::*;
+pub use bvh::*;
 pub use camera::*;
+pub use environment::*;
+pub use framebuffer::*;
 use image::RgbaImage;
 pub use lighting::*;
 pub use material::*;
+pub use mesh::*;
+pub use path_tracer::*;
+#[cfg(feature = "gtk")]
+pub use pixbuf::*;
+pub use procedural::*;
 pub use render::*;
+pub use sampling::*;
+pub use scene::*;
+pub use scene_file::*;
 pub use shapes::*;
+pub use texture::*;
+pub use tone_mapping::*;
 pub use vector::*;
 
-// Image parameters TODO: ImageParam struct
-pub const IMG_SIZE: u32 = 1000;
-pub const IMG_HEIGHT: u32 = IMG_SIZE;
-pub const IMG_WIDTH: u32 = IMG_SIZE;
+// Image parameters
 pub const BYTES_PER_PIXEL: usize = 4;
-pub const ARRAY_WIDTH: usize = IMG_WIDTH as usize * BYTES_PER_PIXEL;
 
 // Threads
 pub const NUM_THREADS: usize = 10;
@@ -31,7 +55,7 @@ pub fn set_global_rayon_threads(n: usize) {
 }
 
 pub fn black_img(img: &mut RgbaImage) {
-    img.pixels_mut().for_each(|mut p| {
+    img.pixels_mut().for_each(|p| {
         let black = PixelColour::default();
         p.0 = [black.x, black.y, black.z, 255];
     });
@@ -1,22 +1,289 @@
 use crate::shapes::Shape;
-use crate::{ColourChannel, LightColour, PixelColour, Point, Vector3D};
-
-#[derive(Copy, Clone)]
+use crate::{
+    srgb_decode, Bsdf, ColourChannel, LightColour, PixelColour, Point, Scene,
+    Surface, Vector3D, AIR_REFRACTIVE_INDEX,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How far shadow rays are nudged off the surface they start from, so they
+/// don't immediately hit it again due to floating point error
+const SHADOW_EPSILON: f64 = 1e-6;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LightSource {
+    #[serde(default = "default_light_position")]
     pub position: Point,
+    #[serde(default = "default_light_colour")]
+    pub colour: LightColour,
+    #[serde(flatten)]
+    pub kind: LightKind,
+    #[serde(default)]
+    pub attenuation: Attenuation,
+}
+
+/// The shape of a light and the way it casts light into the scene. Scene
+/// files pick one with the light's `type`, e.g. `type = "spot"`
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightKind {
+    /// Shines equally in every direction from a single point
+    Point,
+    /// Infinitely far away, so every ray of light travels in the same
+    /// `direction`, like the sun. The light's position is ignored
+    Directional { direction: Vector3D },
+    /// Shines from a point in a cone around `direction`. `cone_angle` is the
+    /// angle in degrees between the direction and the edge of the cone, and
+    /// `falloff` is the width in degrees of the band inside the edge over
+    /// which the light fades out
+    Spot {
+        direction: Vector3D,
+        cone_angle: f64,
+        falloff: f64,
+    },
+    /// A rectangle centered on the light's position with edges `u` and `v`,
+    /// sampled on a jittered `samples` x `samples` grid for soft shadows
+    Rectangular {
+        u: Vector3D,
+        v: Vector3D,
+        samples: usize,
+    },
+    /// A sphere centered on the light's position, sampled at `samples` random
+    /// points on the side facing the shaded point for soft shadows
+    Spherical { radius: f64, samples: usize },
+}
+
+/// How light fades with distance, the intensity is divided by
+/// `constant + linear * d + quadratic * d^2`
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Attenuation {
+    pub constant: f64,
+    pub linear: f64,
+    pub quadratic: f64,
+}
+
+impl Attenuation {
+    /// Light doesn't fade at all
+    pub fn none() -> Self {
+        Self::new(1.0, 0.0, 0.0)
+    }
+
+    pub fn new(constant: f64, linear: f64, quadratic: f64) -> Self {
+        Self {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    /// Physically correct inverse square falloff, with the light at full
+    /// intensity `reference_distance` away from it
+    pub fn inverse_square(reference_distance: f64) -> Self {
+        Self::new(0.0, 0.0, 1.0 / (reference_distance * reference_distance))
+    }
+
+    pub fn factor(&self, distance: f64) -> f64 {
+        let denominator = self.constant
+            + self.linear * distance
+            + self.quadratic * distance * distance;
+        if denominator > 0.0 {
+            (1.0 / denominator).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// A single ray of light arriving at a point on a surface
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample {
+    /// Unit vector from the surface towards the light
+    pub direction: Vector3D,
+    /// Distance to the light, infinite for directional lights
+    pub distance: f64,
+    /// Colour and intensity of the light after attenuation
     pub colour: LightColour,
 }
 
 impl Default for LightSource {
     fn default() -> Self {
         Self {
-            position: Point::new(-500.0, -350.0, -350.0),
-            colour: LightColour::new(1.0, 1.0, 1.0),
+            position: default_light_position(),
+            colour: default_light_colour(),
+            kind: LightKind::Point,
+            attenuation: Attenuation::none(),
         }
     }
 }
 
+fn default_light_position() -> Point {
+    Point::new(-500.0, -350.0, -350.0)
+}
+
+fn default_light_colour() -> LightColour {
+    LightColour::new(1.0, 1.0, 1.0)
+}
+
 impl LightSource {
+    pub fn point(position: Point, colour: LightColour) -> Self {
+        Self {
+            position,
+            colour,
+            ..Self::default()
+        }
+    }
+
+    pub fn directional(mut direction: Vector3D, colour: LightColour) -> Self {
+        direction.normalise();
+        Self {
+            colour,
+            kind: LightKind::Directional { direction },
+            ..Self::default()
+        }
+    }
+
+    pub fn spot(
+        position: Point,
+        mut direction: Vector3D,
+        cone_angle: f64,
+        falloff: f64,
+        colour: LightColour,
+    ) -> Self {
+        direction.normalise();
+        Self {
+            position,
+            colour,
+            kind: LightKind::Spot {
+                direction,
+                cone_angle,
+                falloff,
+            },
+            ..Self::default()
+        }
+    }
+
+    pub fn rectangular(
+        position: Point,
+        u: Vector3D,
+        v: Vector3D,
+        samples: usize,
+        colour: LightColour,
+    ) -> Self {
+        Self {
+            position,
+            colour,
+            kind: LightKind::Rectangular { u, v, samples },
+            ..Self::default()
+        }
+    }
+
+    pub fn spherical(
+        position: Point,
+        radius: f64,
+        samples: usize,
+        colour: LightColour,
+    ) -> Self {
+        Self {
+            position,
+            colour,
+            kind: LightKind::Spherical { radius, samples },
+            ..Self::default()
+        }
+    }
+
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    /// The light arriving at `point` from this light source. Point-like
+    /// lights give a single sample, area lights give one per sampled point
+    /// with the colour split evenly between them, picked with `rng`
+    pub fn samples(
+        &self,
+        point: &Point,
+        rng: &mut impl Rng,
+    ) -> Vec<LightSample> {
+        match self.kind {
+            LightKind::Point => {
+                vec![self.sample_from(self.position, point, 1.0)]
+            }
+            LightKind::Directional { direction } => vec![LightSample {
+                direction: direction * -1.0,
+                distance: f64::INFINITY,
+                colour: self.colour,
+            }],
+            LightKind::Spot {
+                direction,
+                cone_angle,
+                falloff,
+            } => {
+                let mut sample = self.sample_from(self.position, point, 1.0);
+                let cos_angle = (sample.direction * -1.0).dot(&direction);
+                sample.colour =
+                    sample.colour * spot_factor(cos_angle, cone_angle, falloff);
+                vec![sample]
+            }
+            LightKind::Rectangular { u, v, samples } => {
+                let samples = samples.max(1);
+                let weight = 1.0 / (samples * samples) as f64;
+                let corner = self.position - (u / 2.0) - (v / 2.0);
+                let mut light_samples = Vec::with_capacity(samples * samples);
+                for i in 0..samples {
+                    for j in 0..samples {
+                        let s = (i as f64 + rng.gen::<f64>()) / samples as f64;
+                        let t = (j as f64 + rng.gen::<f64>()) / samples as f64;
+                        let source = corner + u * s + v * t;
+                        light_samples
+                            .push(self.sample_from(source, point, weight));
+                    }
+                }
+                light_samples
+            }
+            LightKind::Spherical { radius, samples } => {
+                let samples = samples.max(1);
+                let weight = 1.0 / samples as f64;
+                (0..samples)
+                    .map(|_| {
+                        let mut offset = random_unit_vector(rng);
+                        // Only the side of the sphere facing the point is
+                        // visible from it
+                        if offset.dot(&(*point - self.position)) < 0.0 {
+                            offset = offset * -1.0;
+                        }
+                        let source = self.position + offset * radius;
+                        self.sample_from(source, point, weight)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Light travelling from `source` to `point`, attenuated by distance and
+    /// scaled by `weight`
+    fn sample_from(
+        &self,
+        source: Point,
+        point: &Point,
+        weight: f64,
+    ) -> LightSample {
+        let mut direction = source - *point;
+        let distance = direction.magnitude();
+        direction.normalise();
+        LightSample {
+            direction,
+            distance,
+            colour: self.colour * (self.attenuation.factor(distance) * weight),
+        }
+    }
+
     pub fn set_x(&mut self, new_x: f64) {
         self.position.x = new_x;
     }
@@ -33,8 +300,10 @@ impl LightSource {
         self.colour = new_colour.to_light_colour();
     }
 
+    /// Set one channel of the colour from an 8 bit sRGB value, like a
+    /// `PixelColour`
     pub fn set_colour_channel(&mut self, channel: &ColourChannel, value: u8) {
-        let value: f64 = value as f64 / 255.0;
+        let value = srgb_decode(value as f64 / 255.0);
         match channel {
             ColourChannel::Red => {
                 self.colour.x = value;
@@ -49,6 +318,29 @@ impl LightSource {
     }
 }
 
+/// Intensity multiplier for a spotlight, 1.0 inside the cone fading smoothly
+/// to 0.0 at its edge
+fn spot_factor(cos_angle: f64, cone_angle: f64, falloff: f64) -> f64 {
+    let cos_outer = cone_angle.to_radians().cos();
+    let cos_inner = (cone_angle - falloff).max(0.0).to_radians().cos();
+    if cos_angle >= cos_inner {
+        1.0
+    } else if cos_angle <= cos_outer {
+        0.0
+    } else {
+        let x = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+/// Uniformly distributed random direction
+fn random_unit_vector(rng: &mut impl Rng) -> Vector3D {
+    let z: f64 = rng.gen_range(-1.0..=1.0);
+    let phi: f64 = rng.gen_range(0.0..std::f64::consts::TAU);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vector3D::new(r * phi.cos(), r * phi.sin(), z)
+}
+
 #[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Point,
@@ -67,7 +359,6 @@ pub struct Intersection<'a> {
     point: Point,
     object: &'a dyn Shape,
     ray: &'a Ray,
-    light_source: LightSource,
     is_inside: bool,
 }
 
@@ -77,7 +368,6 @@ impl<'a> Intersection<'a> {
         point: Point,
         object: &'a impl Shape,
         ray: &'a Ray,
-        light_source: LightSource,
         is_inside: bool,
     ) -> Self {
         Self {
@@ -85,7 +375,6 @@ impl<'a> Intersection<'a> {
             point,
             object,
             ray,
-            light_source,
             is_inside,
         }
     }
@@ -106,40 +395,208 @@ impl<'a> Intersection<'a> {
         self.ray
     }
 
-    pub fn light_source(&self) -> LightSource {
-        self.light_source
-    }
-
+    /// Phong shading for the intersection, with the diffuse and specular
+    /// contributions of every light summed together. Lights which are blocked
+    /// by another shape only contribute ambient light, unless shadows are
+    /// turned off in the scene's settings. Physically based materials use
+    /// their BSDF in place of the diffuse and specular terms. The result isn't
+    /// clamped, so bright highlights can go above 1.0. Area lights are
+    /// sampled with `rng`
     pub fn phong(
         &self,
         _pixel_point: &Point,
-        ambient_coefficient: f64,
-    ) -> PixelColour {
-        self.phong_diffuse() + self.phong_ambient(ambient_coefficient) + self.phong_specular()
+        scene: &Scene,
+        rng: &mut impl Rng,
+    ) -> LightColour {
+        let lights = scene.lights();
+        let ambient_coefficient = scene.camera().ambient_coefficient();
+        let surface = self.surface();
+        lights
+            .iter()
+            .flat_map(|light| light.samples(&self.point, rng))
+            .filter(|sample| {
+                !scene.settings.shadows || !self.in_shadow(sample, scene)
+            })
+            .fold(
+                self.phong_ambient(&surface, lights, ambient_coefficient),
+                |colour, sample| colour + self.shade(&surface, &sample),
+            )
+    }
+
+    /// Light from the sample scattered towards the ray, by the physically
+    /// based model for materials which have one and Phong's otherwise
+    fn shade(&self, surface: &Surface, sample: &LightSample) -> LightColour {
+        if surface.microfacet.is_some() {
+            Bsdf::new(surface, self.shading_normal())
+                .scatter_light(&self.pixel_direction(), sample)
+        } else {
+            self.phong_diffuse(surface, sample)
+                + self.phong_specular(surface, sample)
+        }
+    }
+
+    /// Texture coordinates of the intersection on the shape
+    pub fn uv(&self) -> (f64, f64) {
+        self.object.uv(&self.point)
+    }
+
+    /// The shape's material at the intersection, with any textures applied
+    pub fn surface(&self) -> Surface {
+        self.object.material().at(&self.point, self.uv())
     }
 
-    fn light_direction(&self) -> Vector3D {
-        let mut direction_l = self.light_source.position - self.point;
-        direction_l.normalise();
+    /// How the surface scatters light at the intersection, on the side the
+    /// ray arrived from
+    pub fn bsdf(&self) -> Bsdf {
+        Bsdf::new(&self.surface(), self.shading_normal())
+    }
 
-        direction_l
+    /// Whether any shape lies between the intersection and the light
+    pub fn in_shadow(&self, sample: &LightSample, scene: &Scene) -> bool {
+        let shadow_ray = self.shadow_ray(sample);
+        scene.is_occluded(&shadow_ray, sample.distance)
     }
 
-    fn n_l_dot(&self) -> f64 {
-        self.light_direction().dot(&self.object.surface_normal(&self.point))
+    /// Ray from the intersection towards a light. The origin is pushed
+    /// slightly off the surface, on the same side as the light
+    fn shadow_ray(&self, sample: &LightSample) -> Ray {
+        self.ray_towards(sample.direction)
     }
 
-    fn reflected_direction(&self) -> Vector3D {
-        let direction_l = self.light_direction();
-        let direction_n = self.object.surface_normal(&self.point);
-        let n_l_dot = self.n_l_dot();
-        let mut direction_r = (direction_n * 2.0 * n_l_dot) - direction_l;
-        // let mut direction_r = direction_l - (direction_n * 2.0 * n_l_dot);
+    /// Ray leaving the intersection in `direction`. The origin is pushed
+    /// slightly off the surface, on the side the ray is heading
+    pub fn ray_towards(&self, direction: Vector3D) -> Ray {
+        let normal = self.object.surface_normal(&self.point);
+        let offset = if normal.dot(&direction) >= 0.0 {
+            normal * SHADOW_EPSILON
+        } else {
+            normal * -SHADOW_EPSILON
+        };
+
+        Ray {
+            origin: self.point + offset,
+            direction,
+        }
+    }
+
+    fn n_l_dot(&self, sample: &LightSample) -> f64 {
+        sample.direction.dot(&self.shading_normal())
+    }
+
+    /// Direction light from the sample bounces off the surface in
+    fn reflected_direction(&self, sample: &LightSample) -> Vector3D {
+        let direction_l = sample.direction * -1.0;
+        let direction_n = self.shading_normal();
+        let mut direction_r = direction_l.reflect(&direction_n);
         direction_r.normalise();
 
         direction_r
     }
 
+    /// The ray bouncing off the surface as if it were a mirror. The origin is
+    /// pushed slightly off the surface so it doesn't hit it again straight
+    /// away
+    pub fn reflected_ray(&self) -> Ray {
+        let mut direction = self.ray.direction.reflect(&self.shading_normal());
+        direction.normalise();
+
+        Ray {
+            origin: self.point + self.facing_normal() * SHADOW_EPSILON,
+            direction,
+        }
+    }
+
+    /// Ray carrying on through a transparent surface, bent by the change in
+    /// refractive index. `None` if the light is totally internally reflected
+    pub fn refracted_ray(&self) -> Option<Ray> {
+        let mut direction = self.ray.direction;
+        direction.normalise();
+        let mut direction =
+            direction.refract(&self.shading_normal(), self.eta())?;
+        direction.normalise();
+
+        Some(Ray {
+            origin: self.point - self.facing_normal() * SHADOW_EPSILON,
+            direction,
+        })
+    }
+
+    /// Fraction of light reflected off a transparent surface rather than
+    /// passing through it, using Schlick's approximation of the Fresnel
+    /// equations
+    pub fn fresnel(&self) -> f64 {
+        let normal = self.shading_normal();
+        let mut direction = self.ray.direction;
+        direction.normalise();
+        let eta = self.eta();
+        let mut cos = -direction.dot(&normal);
+        if eta > 1.0 {
+            // Leaving the denser material, so the angle that matters is the
+            // one on the far side of the surface
+            let sin2_t = eta * eta * (1.0 - cos * cos);
+            if sin2_t > 1.0 {
+                return 1.0;
+            }
+            cos = (1.0 - sin2_t).sqrt();
+        }
+        let r0 = ((eta - 1.0) / (eta + 1.0)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+    }
+
+    /// Whether the ray is on its way out of the shape it hit, e.g. leaving
+    /// the inside of a glass sphere
+    fn is_exiting(&self) -> bool {
+        self.is_inside
+            || self
+                .object
+                .surface_normal(&self.point)
+                .dot(&self.ray.direction)
+                > 0.0
+    }
+
+    /// Ratio of the refractive index the ray is leaving to the one it's
+    /// entering. Everything outside a shape is treated as air
+    fn eta(&self) -> f64 {
+        let index =
+            self.object.material().refractive_index() / AIR_REFRACTIVE_INDEX;
+        if self.is_exiting() {
+            index
+        } else {
+            1.0 / index
+        }
+    }
+
+    /// Surface normal turned to face the side the ray arrived from, e.g. for
+    /// the inside of a sphere or the back of a plane
+    pub fn facing_normal(&self) -> Vector3D {
+        let normal = self.object.surface_normal(&self.point);
+        if normal.dot(&self.ray.direction) > 0.0 {
+            normal * -1.0
+        } else {
+            normal
+        }
+    }
+
+    /// Normal used for shading, tilted by the material's bump and normal
+    /// maps and turned to face the side the ray arrived from like
+    /// `facing_normal`
+    pub fn shading_normal(&self) -> Vector3D {
+        let normal = self.mapped_normal();
+        let outward = self.object.surface_normal(&self.point);
+        if outward.dot(&self.ray.direction) > 0.0 {
+            normal * -1.0
+        } else {
+            normal
+        }
+    }
+
+    /// The shape's normal tilted by its material's bump and normal maps
+    fn mapped_normal(&self) -> Vector3D {
+        self.object
+            .material()
+            .shading_normal(self.object, &self.point)
+    }
+
     fn pixel_direction(&self) -> Vector3D {
         let mut direction_p = self.ray.origin - self.point;
         direction_p.normalise();
@@ -147,132 +604,271 @@ impl<'a> Intersection<'a> {
         direction_p
     }
 
-    fn phong_ambient(&self, ambient_coefficient: f64) -> PixelColour {
+    /// Ambient light is the same everywhere, so rather than adding some for
+    /// every light it takes on the average colour of all of them
+    fn phong_ambient(
+        &self,
+        surface: &Surface,
+        lights: &[LightSource],
+        ambient_coefficient: f64,
+    ) -> LightColour {
+        if lights.is_empty() {
+            return LightColour::default();
+        }
+        let colour_l = lights
+            .iter()
+            .fold(LightColour::default(), |sum, light| sum + light.colour)
+            / lights.len() as f64;
+        let colour_k = surface.colour * ambient_coefficient;
+        let ambient = colour_k.mul(&colour_l);
         if self.is_inside {
-            PixelColour::new(
-                self.phong_ambient_colour_channel(
-                    ColourChannel::Red,
-                    ambient_coefficient,
-                ),
-                self.phong_ambient_colour_channel(
-                    ColourChannel::Green,
-                    ambient_coefficient,
-                ),
-                self.phong_ambient_colour_channel(
-                    ColourChannel::Blue,
-                    ambient_coefficient,
-                ),
-            ) / 2
+            ambient / 2.0
         } else {
-            PixelColour::new(
-                self.phong_ambient_colour_channel(
-                    ColourChannel::Red,
-                    ambient_coefficient,
-                ),
-                self.phong_ambient_colour_channel(
-                    ColourChannel::Green,
-                    ambient_coefficient,
-                ),
-                self.phong_ambient_colour_channel(
-                    ColourChannel::Blue,
-                    ambient_coefficient,
-                ),
-            )
+            ambient
         }
     }
 
-    fn phong_ambient_colour_channel(
+    fn phong_diffuse(
         &self,
-        channel: ColourChannel,
-        ambient_coefficient: f64,
-    ) -> u8 {
-        let colour_k = self
-            .object
-            .material()
-            .ambient_k(ambient_coefficient)
-            .colour(&channel);
-        let colour_l = self.light_source.colour.colour(&channel);
-        (colour_k * colour_l * 255.0) as u8
-    }
+        surface: &Surface,
+        sample: &LightSample,
+    ) -> LightColour {
+        let diffuse_k = surface.colour;
+        let n_l_dot = self.n_l_dot(sample).clamp(0.0, 1.0);
 
-    fn phong_diffuse(&self) -> PixelColour {
-        let n_l_dot = self.n_l_dot();
-        PixelColour::new(
-            self.phong_diffuse_colour_channel(ColourChannel::Red, n_l_dot),
-            self.phong_diffuse_colour_channel(ColourChannel::Green, n_l_dot),
-            self.phong_diffuse_colour_channel(ColourChannel::Blue, n_l_dot),
-        )
+        diffuse_k.mul(&sample.colour) * n_l_dot
     }
 
-    fn phong_diffuse_colour_channel(
+    fn phong_specular(
         &self,
-        channel: ColourChannel,
-        n_l_dot: f64,
-    ) -> u8 {
-        let diffuse_k = self.object().material().colour();
-        let colour_k = diffuse_k.colour(&channel);
-        let colour_l = self.light_source.colour.colour(&channel);
-
-        let n_l_dot = if n_l_dot < 0.0 {
-            0.0
-        } else if n_l_dot > 1.0 {
-            1.0
+        surface: &Surface,
+        sample: &LightSample,
+    ) -> LightColour {
+        if self.n_l_dot(sample) < 0.0 {
+            return LightColour::default();
+        }
+        let specular_k = surface.specular;
+        let direction_r = self.reflected_direction(sample);
+        let direction_p = self.pixel_direction();
+        let alignment = direction_r.dot(&direction_p);
+        let specular_coefficient =
+            self.object().material().specular_coefficient();
+
+        if alignment < 0.0 {
+            LightColour::default()
         } else {
-            n_l_dot
-        };
+            specular_k.mul(&sample.colour)
+                * alignment.powf(specular_coefficient)
+        }
+    }
+}
 
-        (colour_l * colour_k * n_l_dot * 255.0) as u8
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ImageTexture, Material, Pattern, Plane, ProceduralTexture, Shape,
+        Sphere, WrapMode, BURGUNDY,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn white() -> LightColour {
+        LightColour::new(1.0, 1.0, 1.0)
     }
 
-    fn phong_specular(&self) -> PixelColour {
-        if self.n_l_dot() < 0.0 {
-            PixelColour::new(0, 0, 0)
-        } else {
-            let specular_k = self.object().material().specular_k();
-            let direction_r = self.reflected_direction();
-            let direction_p = self.pixel_direction();
-            let alignment = direction_r.dot(&direction_p);
-            let specular_coefficient =
-                self.object().material().specular_coefficient();
-
-            if alignment < 0.0 {
-                PixelColour::new(0, 0, 0)
-            } else {
-                PixelColour::new(
-                    self.phong_specular_colour_channel(
-                        ColourChannel::Red,
-                        &specular_k,
-                        alignment,
-                        specular_coefficient
-                    ),
-                    self.phong_specular_colour_channel(
-                        ColourChannel::Green,
-                        &specular_k,
-                        alignment,
-                        specular_coefficient
-                    ),
-                    self.phong_specular_colour_channel(
-                        ColourChannel::Blue,
-                        &specular_k,
-                        alignment,
-                        specular_coefficient
-                    ),
-                )
-            }
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(1)
+    }
+
+    #[test]
+    fn directional_light_comes_from_opposite_direction() {
+        let light =
+            LightSource::directional(Vector3D::new(0.0, -2.0, 0.0), white());
+        let samples = light.samples(&Point::new(100.0, 0.0, 0.0), &mut rng());
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].direction, Vector3D::new(0.0, 1.0, 0.0));
+        assert_eq!(samples[0].distance, f64::INFINITY);
+    }
+
+    #[test]
+    fn spot_light_only_lights_inside_cone() {
+        let light = LightSource::spot(
+            Point::new(0.0, 100.0, 0.0),
+            Vector3D::new(0.0, -1.0, 0.0),
+            30.0,
+            5.0,
+            white(),
+        );
+        let inside = light.samples(&Point::new(0.0, 0.0, 0.0), &mut rng());
+        let outside = light.samples(&Point::new(100.0, 0.0, 0.0), &mut rng());
+        assert_eq!(inside[0].colour, white());
+        assert_eq!(outside[0].colour, LightColour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn colour_channels_are_set_in_srgb() {
+        let mut material = Material::new(LightColour::default(), 10.0);
+        let mut light = LightSource::default();
+        for (channel, value) in [
+            (ColourChannel::Red, BURGUNDY.x),
+            (ColourChannel::Green, BURGUNDY.y),
+            (ColourChannel::Blue, BURGUNDY.z),
+        ] {
+            material.set_colour_channel(&channel, value);
+            light.set_colour_channel(&channel, value);
         }
+        assert_eq!(material.colour(), BURGUNDY.to_light_colour());
+        assert_eq!(light.colour, BURGUNDY.to_light_colour());
+        assert_eq!(PixelColour::from_light_colour(&light.colour), BURGUNDY);
     }
 
-    fn phong_specular_colour_channel(
-        &self,
-        channel: ColourChannel,
-        specular_k: &LightColour,
-        alignment: f64,
-        specular_coefficient: f64
-    ) -> u8 {
-        let colour_k = specular_k.colour(&channel);
-        let colour_l = self.light_source.colour.colour(&channel);
-        let alignment_coefficient = alignment.powf(specular_coefficient);
-
-        (colour_k * colour_l * alignment_coefficient * 255.0) as u8
+    #[test]
+    fn spot_light_fades_at_edge() {
+        assert_eq!(spot_factor(29.0_f64.to_radians().cos(), 30.0, 0.0), 1.0);
+        let edge = spot_factor(27.5_f64.to_radians().cos(), 30.0, 5.0);
+        assert!(edge > 0.0 && edge < 1.0);
+    }
+
+    #[test]
+    fn attenuation_reduces_intensity_with_distance() {
+        let light = LightSource::point(Point::new(0.0, 0.0, 0.0), white())
+            .with_attenuation(Attenuation::inverse_square(100.0));
+        let near = light.samples(&Point::new(0.0, 0.0, 50.0), &mut rng());
+        let far = light.samples(&Point::new(0.0, 0.0, 200.0), &mut rng());
+        assert_eq!(near[0].colour, white());
+        assert_eq!(far[0].colour, white() * 0.25);
+    }
+
+    #[test]
+    fn area_light_samples_share_intensity() {
+        let light = LightSource::rectangular(
+            Point::new(0.0, 100.0, 0.0),
+            Vector3D::new(50.0, 0.0, 0.0),
+            Vector3D::new(0.0, 0.0, 50.0),
+            4,
+            white(),
+        );
+        let samples = light.samples(&Point::new(0.0, 0.0, 0.0), &mut rng());
+        assert_eq!(samples.len(), 16);
+        let total = samples
+            .iter()
+            .fold(LightColour::default(), |sum, s| sum + s.colour);
+        assert!((total.x - 1.0).abs() < 1e-9);
+        assert!(samples.iter().all(|s| s.direction.y > 0.0));
+    }
+
+    #[test]
+    fn spherical_light_samples_face_the_point() {
+        let center = Point::new(0.0, 100.0, 0.0);
+        let light = LightSource::spherical(center, 10.0, 8, white());
+        let point = Point::new(0.0, 0.0, 0.0);
+        for sample in light.samples(&point, &mut rng()) {
+            let source = point + sample.direction * sample.distance;
+            assert!(source.y <= center.y + 1e-9);
+        }
+    }
+
+    fn glass_sphere() -> Sphere {
+        Sphere::new(Point::new(0.0, 0.0, 0.0), 100.0, Material::glass())
+    }
+
+    #[test]
+    fn head_on_ray_passes_straight_through_glass() {
+        let sphere = glass_sphere();
+        let ray = Ray {
+            origin: Point::new(0.0, 0.0, -500.0),
+            direction: Vector3D::new(0.0, 0.0, 1.0),
+        };
+        let entering = sphere.intersection(&ray).unwrap();
+        assert!((entering.fresnel() - 0.04).abs() < 1e-9);
+        let inside = entering.refracted_ray().unwrap();
+        assert!((inside.direction - ray.direction).magnitude() < 1e-9);
+
+        let exiting = sphere.intersection(&inside).unwrap();
+        assert!(exiting.point.z > 0.0);
+        assert!((exiting.fresnel() - 0.04).abs() < 1e-9);
+        let outside = exiting.refracted_ray().unwrap();
+        assert!((outside.direction - ray.direction).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn grazing_ray_is_totally_internally_reflected() {
+        let sphere = glass_sphere();
+        let ray = Ray {
+            origin: Point::new(0.0, 90.0, 0.0),
+            direction: Vector3D::new(0.0, 0.0, 1.0),
+        };
+        let exiting = sphere.intersection(&ray).unwrap();
+        assert!(exiting.refracted_ray().is_none());
+        assert_eq!(exiting.fresnel(), 1.0);
+    }
+
+    /// Normal of a floor with `material` where a ray coming straight down
+    /// hits it, halfway along the u texture coordinate, and the floor's
+    /// tangent along u
+    fn floor_normal(material: Material) -> (Vector3D, Vector3D) {
+        let up = Vector3D::new(0.0, 1.0, 0.0);
+        let floor = Plane::new(Point::new(0.0, 0.0, 0.0), up, material);
+        let (tangent, _) = up.orthonormal_basis();
+        let ray = Ray {
+            origin: tangent * 50.0 + up * 100.0,
+            direction: up * -1.0,
+        };
+        let hit = floor.intersection(&ray).unwrap();
+        (hit.shading_normal(), tangent)
+    }
+
+    #[test]
+    fn bump_map_tilts_normal_down_slope() {
+        // Rises from black to white across the middle of the texture, which
+        // is 100 across, so 50 high gives a 45 degree slope
+        let mut image = image::RgbImage::new(2, 1);
+        image.put_pixel(1, 0, image::Rgb([255, 255, 255]));
+        let ramp = ImageTexture::new("")
+            .with_image(&image.into())
+            .with_wrap(WrapMode::Clamp);
+        let material = Material::default().with_bump_map(ramp, 50.0);
+        let (normal, tangent) = floor_normal(material);
+        let mut expected = Vector3D::new(0.0, 1.0, 0.0) - tangent;
+        expected.normalise();
+        assert!((normal - expected).magnitude() < 1e-6, "{normal:?}");
+    }
+
+    #[test]
+    fn normal_map_is_in_tangent_space() {
+        let flat_colour = |colour: LightColour| {
+            ProceduralTexture::new(Pattern::Checker)
+                .with_colours(colour, colour)
+        };
+        let flat = Material::default()
+            .with_normal_map(flat_colour(LightColour::new(0.5, 0.5, 1.0)));
+        let (normal, _) = floor_normal(flat);
+        assert!((normal - Vector3D::new(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+
+        let along_u = Material::default()
+            .with_normal_map(flat_colour(LightColour::new(1.0, 0.5, 0.5)));
+        let (normal, tangent) = floor_normal(along_u);
+        assert!((normal - tangent).magnitude() < 1e-9, "{normal:?}");
+    }
+
+    #[test]
+    fn underside_of_plane_is_lit_from_below() {
+        let up = Vector3D::new(0.0, 1.0, 0.0);
+        let material = Material::new(white(), 10.0);
+        let floor = Plane::new(Point::new(0.0, 0.0, 0.0), up, material);
+        let ray = Ray {
+            origin: Point::new(0.0, -100.0, 0.0),
+            direction: up,
+        };
+        let hit = floor.intersection(&ray).unwrap();
+        let sample = LightSample {
+            direction: up * -1.0,
+            distance: f64::INFINITY,
+            colour: white(),
+        };
+        let surface = hit.surface();
+        assert_eq!(hit.phong_diffuse(&surface, &sample), white());
+        assert_eq!(hit.phong_specular(&surface, &sample), white());
     }
 }
@@ -1,4 +1,9 @@
-use crate::{ColourChannel, LightColour, PixelColour};
+use crate::{
+    srgb_decode, ColourChannel, ImageTexture, LightColour, Microfacet,
+    PixelColour, Point, Shape, TangentFrame, Texture, Vector3D,
+};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
 
 // Colours
 pub const ZIMA_BLUE: PixelColour = PixelColour {
@@ -18,40 +23,194 @@ pub const BURNT_ORANGE: PixelColour = PixelColour {
 };
 
 const DEFAULT_SPECULAR_COEFFICIENT: f64 = 10.0;
+const DEFAULT_BUMP_HEIGHT: f64 = 1.0;
+
+/// Reflectance of non-metals looking straight at them, which is close to
+/// this for most of them
+const DIELECTRIC_F0: f64 = 0.04;
+
+/// Step in texture coordinates used to find the slope of bump maps
+const BUMP_STEP: f64 = 1e-3;
+
+// Indices of refraction
+pub const AIR_REFRACTIVE_INDEX: f64 = 1.0;
+pub const WATER_REFRACTIVE_INDEX: f64 = 1.33;
+pub const GLASS_REFRACTIVE_INDEX: f64 = 1.5;
 
 #[allow(dead_code)]
-#[derive(Copy, Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Material {
     specular_coefficient: f64,
-    pub specular_k: LightColour,
     pub colour: LightColour,
+    /// How much of the colour comes from reflections, from 0.0 (none) to 1.0
+    /// (a perfect mirror)
+    #[serde(deserialize_with = "fraction")]
+    reflectivity: f64,
+    /// How much light passes through the surface rather than being shaded,
+    /// from 0.0 (opaque) to 1.0 (completely clear)
+    #[serde(deserialize_with = "fraction")]
+    transparency: f64,
+    /// How much light bends when it passes into the material from air
+    #[serde(deserialize_with = "refractive_index")]
+    refractive_index: f64,
+    /// Light given off by the surface itself, on top of any it reflects.
+    /// Only the path tracer lets it light up other shapes
+    #[serde(deserialize_with = "emission")]
+    emission: LightColour,
+    /// Metallic-roughness parameters which switch the material from Phong
+    /// shading to the physically based model
+    #[serde(deserialize_with = "microfacet")]
+    microfacet: Option<Microfacet>,
+    /// Texture multiplied with `colour`
+    albedo_texture: Option<Texture>,
+    /// Texture multiplied with the colour of specular highlights
+    specular_texture: Option<Texture>,
+    /// Texture whose brightness is multiplied with the microfacet roughness
+    roughness_texture: Option<Texture>,
+    /// Tangent-space normals, with red along the u texture coordinate, green
+    /// up the image and blue out of the surface, as in OpenGL
+    normal_map: Option<Texture>,
+    /// Texture whose brightness is the height of the surface, which tilts
+    /// the normal on slopes without moving the surface
+    bump_map: Option<Texture>,
+    /// How far white on the bump map is raised above black, in the same
+    /// units as the scene
+    bump_height: f64,
+}
+
+/// A material's properties at one point on a shape, with its textures
+/// applied
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Surface {
+    pub colour: LightColour,
+    /// Colour of specular highlights, see `Material::specular_k`
+    pub specular: LightColour,
+    pub microfacet: Option<Microfacet>,
 }
 
 impl Material {
-    pub fn new(
-        colour: LightColour,
-        specular_coefficient: f64,
-    ) -> Self {
+    pub fn new(colour: LightColour, specular_coefficient: f64) -> Self {
         Self {
-            specular_k: LightColour::new(1.0, 1.0, 1.0),
             specular_coefficient,
             colour,
+            reflectivity: 0.0,
+            transparency: 0.0,
+            refractive_index: AIR_REFRACTIVE_INDEX,
+            emission: LightColour::default(),
+            microfacet: None,
+            albedo_texture: None,
+            specular_texture: None,
+            roughness_texture: None,
+            normal_map: None,
+            bump_map: None,
+            bump_height: DEFAULT_BUMP_HEIGHT,
         }
     }
 
+    pub fn with_reflectivity(mut self, reflectivity: f64) -> Self {
+        self.set_reflectivity(reflectivity);
+        self
+    }
+
+    pub fn with_transparency(
+        mut self,
+        transparency: f64,
+        refractive_index: f64,
+    ) -> Self {
+        self.set_transparency(transparency);
+        self.set_refractive_index(refractive_index);
+        self
+    }
+
+    pub fn with_emission(mut self, emission: LightColour) -> Self {
+        self.set_emission(emission);
+        self
+    }
+
+    pub fn with_microfacet(mut self, microfacet: Microfacet) -> Self {
+        self.set_microfacet(Some(microfacet));
+        self
+    }
+
+    pub fn with_albedo_texture(mut self, texture: impl Into<Texture>) -> Self {
+        self.albedo_texture = Some(texture.into());
+        self
+    }
+
+    pub fn with_specular_texture(
+        mut self,
+        texture: impl Into<Texture>,
+    ) -> Self {
+        self.specular_texture = Some(texture.into());
+        self
+    }
+
+    pub fn with_roughness_texture(
+        mut self,
+        texture: impl Into<Texture>,
+    ) -> Self {
+        self.roughness_texture = Some(texture.into());
+        self
+    }
+
+    pub fn with_normal_map(mut self, texture: impl Into<Texture>) -> Self {
+        self.normal_map = Some(texture.into());
+        self
+    }
+
+    pub fn with_bump_map(
+        mut self,
+        texture: impl Into<Texture>,
+        height: f64,
+    ) -> Self {
+        self.bump_map = Some(texture.into());
+        self.bump_height = height;
+        self
+    }
+
+    /// A clear, slightly tinted glass
+    pub fn glass() -> Self {
+        Self::new(LightColour::new(0.9, 0.95, 1.0), 100.0)
+            .with_transparency(0.9, GLASS_REFRACTIVE_INDEX)
+    }
+
+    /// Clear water with a blue tint
+    pub fn water() -> Self {
+        Self::new(LightColour::new(0.6, 0.8, 1.0), 50.0)
+            .with_transparency(0.85, WATER_REFRACTIVE_INDEX)
+    }
+
     pub fn default_with_colour(colour: PixelColour) -> Self {
-        let mut material = Self::default();
-        material.colour = colour.to_light_colour();
-        material
+        Self {
+            colour: colour.to_light_colour(),
+            ..Self::default()
+        }
     }
 
     pub fn specular_coefficient(&self) -> f64 {
         self.specular_coefficient
     }
 
+    /// Colour of specular highlights. For physically based materials this is
+    /// the reflectance looking straight at the surface, which is the colour
+    /// for metals and close to black for anything else
     pub fn specular_k(&self) -> LightColour {
-        // self.specular_k
-        self.colour
+        self.specular_for(self.colour)
+    }
+
+    fn specular_for(&self, colour: LightColour) -> LightColour {
+        match self.microfacet {
+            Some(Microfacet { metallic, .. }) => {
+                let dielectric = LightColour::new(
+                    DIELECTRIC_F0,
+                    DIELECTRIC_F0,
+                    DIELECTRIC_F0,
+                );
+                dielectric * (1.0 - metallic) + colour * metallic
+            }
+            None => colour,
+        }
     }
 
     pub fn ambient_k(&self, ambient_coefficient: f64) -> LightColour {
@@ -70,8 +229,211 @@ impl Material {
         self.specular_coefficient = new_coefficient;
     }
 
+    pub fn reflectivity(&self) -> f64 {
+        self.reflectivity
+    }
+
+    pub fn set_reflectivity(&mut self, new_value: f64) {
+        self.reflectivity = new_value.clamp(0.0, 1.0);
+    }
+
+    pub fn transparency(&self) -> f64 {
+        self.transparency
+    }
+
+    pub fn set_transparency(&mut self, new_value: f64) {
+        self.transparency = new_value.clamp(0.0, 1.0);
+    }
+
+    pub fn refractive_index(&self) -> f64 {
+        self.refractive_index
+    }
+
+    /// Indices below 1.0 aren't physical, so are raised to 1.0
+    pub fn set_refractive_index(&mut self, new_value: f64) {
+        self.refractive_index = new_value.max(AIR_REFRACTIVE_INDEX);
+    }
+
+    pub fn emission(&self) -> LightColour {
+        self.emission
+    }
+
+    /// Negative emission isn't physical, so is raised to 0.0
+    pub fn set_emission(&mut self, new_value: LightColour) {
+        self.emission = LightColour::new(
+            new_value.x.max(0.0),
+            new_value.y.max(0.0),
+            new_value.z.max(0.0),
+        );
+    }
+
+    pub fn microfacet(&self) -> Option<Microfacet> {
+        self.microfacet
+    }
+
+    /// `None` goes back to Phong shading. Parameters are clamped between 0.0
+    /// and 1.0
+    pub fn set_microfacet(&mut self, new_value: Option<Microfacet>) {
+        self.microfacet = new_value.map(|m| Microfacet {
+            metallic: m.metallic.clamp(0.0, 1.0),
+            roughness: m.roughness.clamp(0.0, 1.0),
+        });
+    }
+
+    pub fn albedo_texture(&self) -> Option<&Texture> {
+        self.albedo_texture.as_ref()
+    }
+
+    pub fn specular_texture(&self) -> Option<&Texture> {
+        self.specular_texture.as_ref()
+    }
+
+    pub fn roughness_texture(&self) -> Option<&Texture> {
+        self.roughness_texture.as_ref()
+    }
+
+    /// Every image texture the material uses, e.g. to load their images
+    pub fn image_textures_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut ImageTexture> {
+        [
+            &mut self.albedo_texture,
+            &mut self.specular_texture,
+            &mut self.roughness_texture,
+            &mut self.normal_map,
+            &mut self.bump_map,
+        ]
+        .into_iter()
+        .filter_map(|texture| match texture {
+            Some(Texture::Image(image)) => Some(image),
+            _ => None,
+        })
+    }
+
+    /// The material's properties at `point` in the scene, which is at
+    /// texture coordinates `uv` on the shape
+    pub fn at(&self, point: &Point, uv: (f64, f64)) -> Surface {
+        let textured = |colour: LightColour, texture: &Option<Texture>| {
+            texture.as_ref().map_or(colour, |texture| {
+                colour.mul(&texture.sample(point, uv))
+            })
+        };
+        let microfacet = self.microfacet.map(|microfacet| {
+            let roughness = textured(
+                LightColour::new(1.0, 1.0, 1.0),
+                &self.roughness_texture,
+            );
+            Microfacet {
+                roughness: microfacet.roughness
+                    * (roughness.x + roughness.y + roughness.z)
+                    / 3.0,
+                ..microfacet
+            }
+        });
+        let colour = textured(self.colour, &self.albedo_texture);
+        Surface {
+            colour,
+            specular: textured(
+                self.specular_for(colour),
+                &self.specular_texture,
+            ),
+            microfacet,
+        }
+    }
+
+    /// The normal used for shading at `point` on `shape`, its surface normal
+    /// tilted by the bump map and then the normal map
+    pub fn shading_normal(&self, shape: &dyn Shape, point: &Point) -> Vector3D {
+        let normal = shape.surface_normal(point);
+        if self.bump_map.is_none() && self.normal_map.is_none() {
+            return normal;
+        }
+        let uv = shape.uv(point);
+        let frame = shape.tangent_frame(point);
+        let normal = match &self.bump_map {
+            Some(bump_map) => self.bumped(bump_map, normal, point, uv, &frame),
+            None => normal,
+        };
+        match &self.normal_map {
+            Some(normal_map) => {
+                let mapped = normal_map.sample(point, uv) * 2.0
+                    - LightColour::new(1.0, 1.0, 1.0);
+                let mut tangent = frame.dpdu - normal * normal.dot(&frame.dpdu);
+                tangent.normalise();
+                // Green points up the image, which is down the v coordinate
+                let mut bitangent = normal * tangent;
+                if bitangent.dot(&frame.dpdv) > 0.0 {
+                    bitangent = bitangent * -1.0;
+                }
+                let mut mapped_normal = tangent * mapped.x
+                    + bitangent * mapped.y
+                    + normal * mapped.z;
+                mapped_normal.normalise();
+                mapped_normal
+            }
+            None => normal,
+        }
+    }
+
+    /// `normal` tilted by the slope of the bump map, as if the surface were
+    /// raised by it (Blinn's bump mapping)
+    fn bumped(
+        &self,
+        bump_map: &Texture,
+        normal: Vector3D,
+        point: &Point,
+        (u, v): (f64, f64),
+        frame: &TangentFrame,
+    ) -> Vector3D {
+        let cross = frame.dpdu * frame.dpdv;
+        let area = cross.magnitude();
+        if area < f64::EPSILON {
+            return normal;
+        }
+        let height = |point: Point, uv: (f64, f64)| {
+            let colour = bump_map.sample(&point, uv);
+            (colour.x + colour.y + colour.z) / 3.0 * self.bump_height
+        };
+        let here = height(*point, (u, v));
+        let slope_u =
+            (height(*point + frame.dpdu * BUMP_STEP, (u + BUMP_STEP, v))
+                - here)
+                / BUMP_STEP;
+        let slope_v =
+            (height(*point + frame.dpdv * BUMP_STEP, (u, v + BUMP_STEP))
+                - here)
+                / BUMP_STEP;
+        // Flipped if the texture coordinates go round the other way to the
+        // normal
+        let orientation = cross.dot(&normal).signum() / area;
+        let mut bumped = normal
+            + ((normal * frame.dpdv) * slope_u
+                + (frame.dpdu * normal) * slope_v)
+                * orientation;
+        bumped.normalise();
+        bumped
+    }
+
+    pub fn normal_map(&self) -> Option<&Texture> {
+        self.normal_map.as_ref()
+    }
+
+    pub fn bump_map(&self) -> Option<&Texture> {
+        self.bump_map.as_ref()
+    }
+
+    pub fn bump_height(&self) -> f64 {
+        self.bump_height
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission != LightColour::default()
+    }
+
+    /// Set one channel of the colour from an 8 bit sRGB value, like a
+    /// `PixelColour`
     pub fn set_colour_channel(&mut self, channel: &ColourChannel, value: u8) {
-        let value: f64 = value as f64 / 255.0;
+        let value = srgb_decode(value as f64 / 255.0);
         match channel {
             ColourChannel::Red => {
                 self.colour.x = value;
@@ -89,9 +451,76 @@ impl Material {
 impl Default for Material {
     fn default() -> Self {
         Self {
-            specular_k: LightColour::new(1.0, 1.0, 1.0),
             specular_coefficient: DEFAULT_SPECULAR_COEFFICIENT,
             colour: BURGUNDY.to_light_colour(),
+            reflectivity: 0.0,
+            transparency: 0.0,
+            refractive_index: AIR_REFRACTIVE_INDEX,
+            emission: LightColour::default(),
+            microfacet: None,
+            albedo_texture: None,
+            specular_texture: None,
+            roughness_texture: None,
+            normal_map: None,
+            bump_map: None,
+            bump_height: DEFAULT_BUMP_HEIGHT,
         }
     }
 }
+
+// Scene files are rejected if they have values the setters would clamp, so
+// mistakes aren't silently changed into something else
+
+fn fraction<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    check_fraction(value).map_err(D::Error::custom)
+}
+
+fn refractive_index<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if value >= AIR_REFRACTIVE_INDEX {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!(
+            "refractive index must be at least {AIR_REFRACTIVE_INDEX}, not {value}"
+        )))
+    }
+}
+
+fn emission<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<LightColour, D::Error> {
+    let value = LightColour::deserialize(deserializer)?;
+    if value.x >= 0.0 && value.y >= 0.0 && value.z >= 0.0 {
+        Ok(value)
+    } else {
+        Err(D::Error::custom("emission can't be negative"))
+    }
+}
+
+fn microfacet<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Microfacet>, D::Error> {
+    let value = Option::<Microfacet>::deserialize(deserializer)?;
+    if let Some(Microfacet {
+        metallic,
+        roughness,
+    }) = value
+    {
+        check_fraction(metallic).map_err(D::Error::custom)?;
+        check_fraction(roughness).map_err(D::Error::custom)?;
+    }
+    Ok(value)
+}
+
+fn check_fraction(value: f64) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("expected a value from 0.0 to 1.0, not {value}"))
+    }
+}
@@ -0,0 +1,478 @@
+use crate::{
+    Aabb, Bvh, Intersection, LightColour, Material, MaterialRef, Point, Ray,
+    Shape, ShapeDescription, Triangle, Vector3D,
+};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// A shape made up of many triangles, e.g. a model loaded from a Wavefront
+/// `.obj` file
+#[derive(Clone)]
+pub struct Mesh {
+    triangles: Vec<Triangle>,
+    bvh: Bvh,
+    center: Point,
+    material: Material,
+    /// The `.obj` file the mesh was loaded from, if any
+    source: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "{e}"),
+            ObjError::Parse { line, message } => {
+                write!(f, "line {line}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(value: std::io::Error) -> Self {
+        ObjError::Io(value)
+    }
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>, material: Material) -> Self {
+        let mut mesh = Mesh {
+            triangles,
+            bvh: Bvh::new(vec![]),
+            center: Point::default(),
+            material,
+            source: None,
+        };
+        mesh.rebuild_bvh();
+        mesh.center = mesh.bvh.bounds().centroid();
+        mesh
+    }
+
+    /// Load a mesh from a Wavefront `.obj` file. Any material libraries it
+    /// references with `mtllib` are loaded relative to the `.obj` file, faces
+    /// without a material use `Material::default()`
+    pub fn from_obj(path: &Path) -> Result<Self, ObjError> {
+        let source = std::fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut mesh = parse_obj(&source, |mtl_file| {
+            let mtl_source = std::fs::read_to_string(base_dir.join(mtl_file))?;
+            parse_mtl(&mtl_source)
+        })?;
+        mesh.source = Some(path.to_path_buf());
+        Ok(mesh)
+    }
+
+    /// Parse the contents of a `.obj` file, ignoring any material libraries
+    pub fn from_obj_str(source: &str) -> Result<Self, ObjError> {
+        parse_obj(source, |_| Ok(HashMap::new()))
+    }
+
+    /// The `.obj` file written for the mesh when it's saved in a scene file
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    pub fn set_source(&mut self, path: impl Into<PathBuf>) {
+        self.source = Some(path.into());
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    /// Move every triangle in the mesh by `offset`
+    pub fn translate(&mut self, offset: Vector3D) {
+        self.triangles.iter_mut().for_each(|t| t.translate(offset));
+        self.center = self.center + offset;
+        self.rebuild_bvh();
+    }
+
+    /// Scale the mesh about its center
+    pub fn scale(&mut self, factor: f64) {
+        let center = self.center;
+        self.triangles
+            .iter_mut()
+            .for_each(|t| t.scale_about(center, factor));
+        self.rebuild_bvh();
+    }
+
+    fn rebuild_bvh(&mut self) {
+        self.bvh = Bvh::new(
+            self.triangles
+                .iter()
+                .map(|t| t.bounding_box())
+                .enumerate()
+                .collect(),
+        );
+    }
+}
+
+impl Shape for Mesh {
+    fn intersection<'a>(&'a self, ray: &'a Ray) -> Option<Intersection<'a>> {
+        self.bvh
+            .closest_intersect(ray, |i| self.triangles[i].intersection(ray))
+    }
+
+    /// Meshes are shaded using the normals of their individual triangles, so
+    /// this only approximates the normal by pointing away from the center
+    fn surface_normal(&self, point: &Point) -> Vector3D {
+        let mut normal = *point - self.center;
+        normal.normalise();
+        normal
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn position(&self) -> Point {
+        self.center
+    }
+
+    fn set_position(&mut self, new_position: Point) {
+        self.translate(new_position - self.center);
+    }
+
+    fn size(&self) -> f64 {
+        let center = self.center;
+        self.triangles
+            .iter()
+            .flat_map(|t| t.vertices)
+            .map(|v| (v - center).magnitude())
+            .fold(0.0, f64::max)
+    }
+
+    fn adjust_size(&mut self, delta: f64) {
+        let size = self.size();
+        if size > 0.0 {
+            self.scale((size + delta).max(0.0) / size);
+        }
+    }
+
+    fn update_material(&mut self, update: &dyn Fn(&mut Material)) {
+        update(&mut self.material);
+        self.triangles
+            .iter_mut()
+            .for_each(|t| update(&mut t.material));
+    }
+
+    /// Meshes are saved as a reference to their `.obj` file, so ones which
+    /// weren't loaded from a file can't be saved. The material is only saved
+    /// if it's been applied to every triangle, otherwise the materials from
+    /// the file are kept
+    fn description(&self) -> Option<ShapeDescription> {
+        let uniform =
+            self.triangles.iter().all(|t| t.material == self.material);
+        Some(ShapeDescription::Mesh {
+            obj: self.source.clone()?,
+            position: Some(self.center),
+            size: Some(self.size()),
+            material: uniform.then_some(MaterialRef::Inline(Box::new(
+                self.material.clone(),
+            ))),
+        })
+    }
+}
+
+fn parse_obj(
+    source: &str,
+    mut load_mtl: impl FnMut(&str) -> Result<HashMap<String, Material>, ObjError>,
+) -> Result<Mesh, ObjError> {
+    let mut vertices: Vec<Point> = vec![];
+    let mut normals: Vec<Vector3D> = vec![];
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let mut current_material = Material::default();
+    let mut triangles: Vec<Triangle> = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => vertices.push(parse_vector(&args, line_number)?),
+            "vn" => normals.push(parse_vector(&args, line_number)?),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_error(
+                        line_number,
+                        "face needs at least three vertices",
+                    ));
+                }
+                let face = args
+                    .iter()
+                    .map(|a| {
+                        parse_face_vertex(
+                            a,
+                            vertices.len(),
+                            normals.len(),
+                            line_number,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                // Triangulate as a fan around the first vertex
+                for k in 1..face.len() - 1 {
+                    let corners = [face[0], face[k], face[k + 1]];
+                    let points = corners.map(|(v, _)| vertices[v]);
+                    let triangle = match corners {
+                        [(_, Some(n0)), (_, Some(n1)), (_, Some(n2))] => {
+                            Triangle::with_normals(
+                                points,
+                                [normals[n0], normals[n1], normals[n2]],
+                                current_material.clone(),
+                            )
+                        }
+                        _ => Triangle::new(points, current_material.clone()),
+                    };
+                    triangles.push(triangle);
+                }
+            }
+            "mtllib" => {
+                for file in args {
+                    materials.extend(load_mtl(file)?);
+                }
+            }
+            "usemtl" => {
+                let name = args.first().ok_or_else(|| {
+                    parse_error(line_number, "usemtl needs a material name")
+                })?;
+                current_material =
+                    materials.get(*name).cloned().unwrap_or_default();
+            }
+            // Texture coordinates, groups, smoothing etc. aren't used
+            _ => {}
+        }
+    }
+
+    // The mesh's own material is what the GUI shows, so it should match the
+    // triangles when they're all the same
+    let material = match triangles.first() {
+        Some(first)
+            if triangles.iter().all(|t| t.material == first.material) =>
+        {
+            first.material.clone()
+        }
+        _ => Material::default(),
+    };
+    Ok(Mesh::new(triangles, material))
+}
+
+/// Parse the contents of a `.mtl` file, mapping each named material's diffuse
+/// colour (`Kd`) and specular exponent (`Ns`) onto a `Material`. Specular
+/// colours (`Ks`) are ignored, as highlights take the diffuse colour
+fn parse_mtl(source: &str) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            let name = args.first().ok_or_else(|| {
+                parse_error(line_number, "newmtl needs a material name")
+            })?;
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((name.to_string(), Material::default()));
+            continue;
+        }
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None => continue,
+        };
+        match keyword {
+            "Kd" => material.colour = parse_colour(&args, line_number)?,
+            "Ns" => material.set_specular_coefficient(parse_float(
+                args.first().copied(),
+                line_number,
+            )?),
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+/// Parse a single `v`, `v/vt`, `v//vn` or `v/vt/vn` face element into
+/// zero-based (vertex, normal) indices
+fn parse_face_vertex(
+    element: &str,
+    vertex_count: usize,
+    normal_count: usize,
+    line_number: usize,
+) -> Result<(usize, Option<usize>), ObjError> {
+    let mut parts = element.split('/');
+    let vertex = parse_index(parts.next(), vertex_count, line_number)?
+        .ok_or_else(|| parse_error(line_number, "face is missing a vertex"))?;
+    let _texture = parts.next();
+    let normal = parse_index(parts.next(), normal_count, line_number)?;
+    Ok((vertex, normal))
+}
+
+/// OBJ indices start at 1, negative indices count back from the most recent
+fn parse_index(
+    index: Option<&str>,
+    count: usize,
+    line_number: usize,
+) -> Result<Option<usize>, ObjError> {
+    let index = match index {
+        Some(index) if !index.is_empty() => index,
+        _ => return Ok(None),
+    };
+    let value: i64 = index.parse().map_err(|_| {
+        parse_error(line_number, &format!("invalid index '{index}'"))
+    })?;
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        Err(parse_error(
+            line_number,
+            &format!("index {value} is out of range"),
+        ))
+    } else {
+        Ok(Some(resolved as usize))
+    }
+}
+
+fn parse_vector(args: &[&str], line_number: usize) -> Result<Point, ObjError> {
+    if args.len() < 3 {
+        return Err(parse_error(line_number, "expected three coordinates"));
+    }
+    Ok(Point::new(
+        parse_float(Some(args[0]), line_number)?,
+        parse_float(Some(args[1]), line_number)?,
+        parse_float(Some(args[2]), line_number)?,
+    ))
+}
+
+fn parse_colour(
+    args: &[&str],
+    line_number: usize,
+) -> Result<LightColour, ObjError> {
+    parse_vector(args, line_number)
+}
+
+fn parse_float(
+    value: Option<&str>,
+    line_number: usize,
+) -> Result<f64, ObjError> {
+    let value =
+        value.ok_or_else(|| parse_error(line_number, "expected a number"))?;
+    value.parse().map_err(|_| {
+        parse_error(line_number, &format!("invalid number '{value}'"))
+    })
+}
+
+fn parse_error(line: usize, message: &str) -> ObjError {
+    ObjError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "
+        # a unit square in the xy plane
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vn 0 0 -1
+        f 1//1 2//1 3//1 4//1
+    ";
+
+    #[test]
+    fn quad_is_triangulated() {
+        let mesh = Mesh::from_obj_str(QUAD).unwrap();
+        assert_eq!(mesh.triangles().len(), 2);
+        assert!(mesh.triangles().iter().all(|t| t.normals.is_some()));
+        assert_eq!(mesh.position(), Point::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn single_material_is_the_mesh_material() {
+        let red = Material::new(LightColour::new(1.0, 0.0, 0.0), 10.0);
+        let materials = HashMap::from([("red".to_string(), red.clone())]);
+        let source = format!("mtllib red.mtl\nusemtl red\n{QUAD}");
+        let mut mesh = parse_obj(&source, |_| Ok(materials.clone())).unwrap();
+        assert_eq!(mesh.material(), &red);
+        mesh.set_source("quad.obj");
+        assert!(matches!(
+            mesh.description(),
+            Some(ShapeDescription::Mesh {
+                material: Some(_),
+                ..
+            })
+        ));
+
+        let mixed = format!("{source}\nusemtl blue\nf 1 2 3\n");
+        let mesh = parse_obj(&mixed, |_| Ok(materials.clone())).unwrap();
+        assert_eq!(mesh.material(), &Material::default());
+    }
+
+    #[test]
+    fn negative_indices_are_relative() {
+        let mesh = Mesh::from_obj_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1")
+            .unwrap();
+        assert_eq!(mesh.triangles().len(), 1);
+        assert_eq!(mesh.triangles()[0].vertices[0], Point::new(0.0, 0.0, 0.0));
+        assert!(mesh.triangles()[0].normals.is_none());
+    }
+
+    #[test]
+    fn out_of_range_index_reports_line() {
+        let result = Mesh::from_obj_str("v 0 0 0\nv 1 0 0\nf 1 2 3");
+        assert!(matches!(result, Err(ObjError::Parse { line: 3, .. })));
+    }
+
+    #[test]
+    fn materials_are_mapped() {
+        let materials = parse_mtl(
+            "newmtl red\nKd 1.0 0.0 0.0\nKs 0 1 0\nNs 50\n\
+             newmtl blue\nKd 0 0 1",
+        )
+        .unwrap();
+        let red = &materials["red"];
+        assert_eq!(red.colour(), LightColour::new(1.0, 0.0, 0.0));
+        assert_eq!(red.specular_coefficient(), 50.0);
+        assert_eq!(red.specular_k(), red.colour());
+        assert_eq!(materials["blue"].colour(), LightColour::new(0.0, 0.0, 1.0));
+    }
+}
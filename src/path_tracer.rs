@@ -0,0 +1,332 @@
+use crate::{
+    power_heuristic, Bsdf, Intersection, LightColour, LightSample, Point, Ray,
+    Scene, Vector3D,
+};
+use rand::Rng;
+
+/// Bounces after which paths may be cut short by Russian roulette
+const ROULETTE_DEPTH: usize = 3;
+
+/// Lowest chance of a path surviving Russian roulette, so that dim paths
+/// which go on to find something bright aren't always lost
+const MIN_SURVIVAL: f64 = 0.05;
+
+/// Shadow rays towards emissive shapes stop this fraction short of them, so
+/// the shape itself doesn't count as being in the way
+const EMITTER_SHADOW_MARGIN: f64 = 1e-6;
+
+/// How a path arrived at the surface it's just hit, which decides how much
+/// of the light given off by that surface is counted
+#[derive(Copy, Clone)]
+enum Bounce {
+    /// Straight from the camera or off a mirror or glass, which sampling
+    /// the lights could never have found
+    Specular,
+    /// Scattered by the BSDF of a surface at `from`, in a direction picked
+    /// with probability density `pdf`
+    Scattered { from: Point, pdf: f64 },
+}
+
+/// Radiance seen along a ray, estimated by following a single random path
+/// through the scene. Light sources and emissive shapes are sampled directly
+/// wherever the path is scattered by a BSDF, and emissive shapes which are
+/// also hit by chance are weighted against that with multiple importance
+/// sampling
+pub fn trace_path(ray: &Ray, scene: &Scene, rng: &mut impl Rng) -> LightColour {
+    let mut radiance = LightColour::default();
+    let mut throughput = LightColour::new(1.0, 1.0, 1.0);
+    let mut ray = *ray;
+    let mut bounce = Bounce::Specular;
+    for depth in 0..=scene.settings.max_depth {
+        let Some(hit) = scene.closest_intersect(&ray) else {
+            if let Some(environment) = &scene.environment {
+                let weight = match bounce {
+                    Bounce::Specular => 1.0,
+                    Bounce::Scattered { pdf, .. } => {
+                        power_heuristic(pdf, environment.pdf(&ray.direction))
+                    }
+                };
+                let light = environment.radiance(&ray.direction);
+                radiance = radiance + throughput.mul(&light) * weight;
+            }
+            break;
+        };
+        let material = hit.object().material();
+        if material.is_emissive() {
+            let weight = match bounce {
+                Bounce::Specular => 1.0,
+                Bounce::Scattered { from, pdf } => {
+                    let light_pdf =
+                        hit.object().direction_pdf(&from, &ray.direction)
+                            / scene.emitters().len() as f64;
+                    power_heuristic(pdf, light_pdf)
+                }
+            };
+            radiance = radiance + throughput.mul(&material.emission()) * weight;
+        }
+        if depth == scene.settings.max_depth {
+            break;
+        }
+
+        // Whatever isn't refracted or mirrored is scattered by the BSDF
+        let transparency = material.transparency();
+        let reflectivity = material.reflectivity();
+        let scattered = (1.0 - transparency) * (1.0 - reflectivity);
+        let bsdf = hit.bsdf();
+        let mut outgoing = ray.direction * -1.0;
+        outgoing.normalise();
+        if scattered > 0.0 {
+            let direct =
+                direct_light(&hit, &bsdf, &outgoing, scattered, scene, rng);
+            radiance = radiance + throughput.mul(&direct);
+        }
+
+        // Carry on along one of the ways the surface scatters light, picked
+        // in proportion to how much of the light goes that way
+        let choice: f64 = rng.gen();
+        let next = if choice < transparency {
+            let refracted = hit
+                .refracted_ray()
+                .filter(|_| rng.gen::<f64>() >= hit.fresnel());
+            bounce = Bounce::Specular;
+            refracted.unwrap_or_else(|| hit.reflected_ray())
+        } else if choice < 1.0 - scattered {
+            bounce = Bounce::Specular;
+            hit.reflected_ray()
+        } else {
+            // Picking the BSDF as often as it scatters light cancels out its
+            // share of the light
+            let Some(sample) = bsdf.sample(&outgoing, rng) else {
+                break;
+            };
+            throughput = throughput.mul(&sample.weight);
+            bounce = Bounce::Scattered {
+                from: hit.point(),
+                pdf: scattered * sample.pdf,
+            };
+            hit.ray_towards(sample.direction)
+        };
+
+        if depth >= ROULETTE_DEPTH {
+            let survival = throughput
+                .x
+                .max(throughput.y)
+                .max(throughput.z)
+                .clamp(MIN_SURVIVAL, 1.0);
+            if rng.gen::<f64>() >= survival {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+        ray = next;
+    }
+    radiance
+}
+
+/// Light arriving straight from the lights and emissive shapes, scattered
+/// towards `outgoing` by the surface's BSDF, which handles `scattered` of the
+/// light leaving the surface
+fn direct_light(
+    hit: &Intersection,
+    bsdf: &Bsdf,
+    outgoing: &Vector3D,
+    scattered: f64,
+    scene: &Scene,
+    rng: &mut impl Rng,
+) -> LightColour {
+    // Light sources are made of points which rays never hit by chance, so
+    // sampling them is the only way to find them
+    let from_lights = scene
+        .lights()
+        .iter()
+        .flat_map(|light| light.samples(&hit.point(), rng))
+        .filter(|sample| {
+            !scene.settings.shadows || !hit.in_shadow(sample, scene)
+        })
+        .fold(LightColour::default(), |total, sample| {
+            total + bsdf.scatter_light(outgoing, &sample)
+        });
+    from_lights * scattered
+        + from_emitter(hit, bsdf, outgoing, scattered, scene, rng)
+        + from_environment(hit, bsdf, outgoing, scattered, scene, rng)
+}
+
+/// Light from one randomly picked emissive shape, weighted against the
+/// chance of the BSDF finding it instead
+fn from_emitter(
+    hit: &Intersection,
+    bsdf: &Bsdf,
+    outgoing: &Vector3D,
+    scattered: f64,
+    scene: &Scene,
+    rng: &mut impl Rng,
+) -> LightColour {
+    let emitters = scene.emitters();
+    if emitters.is_empty() {
+        return LightColour::default();
+    }
+    let shape = scene.shape(emitters[rng.gen_range(0..emitters.len())]);
+    let Some(sample) =
+        shape.sample_direction(&hit.point(), (rng.gen(), rng.gen()))
+    else {
+        return LightColour::default();
+    };
+    let cos = sample.direction.dot(&hit.shading_normal());
+    if cos <= 0.0 || sample.pdf <= 0.0 {
+        return LightColour::default();
+    }
+    let shadow = LightSample {
+        direction: sample.direction,
+        distance: sample.distance * (1.0 - EMITTER_SHADOW_MARGIN),
+        colour: LightColour::default(),
+    };
+    if scene.settings.shadows && hit.in_shadow(&shadow, scene) {
+        return LightColour::default();
+    }
+
+    let light_pdf = sample.pdf / emitters.len() as f64;
+    let bsdf_pdf = scattered * bsdf.pdf(outgoing, &sample.direction);
+    let weight = power_heuristic(light_pdf, bsdf_pdf);
+    bsdf.eval(outgoing, &sample.direction)
+        .mul(&shape.material().emission())
+        * (scattered * cos * weight / light_pdf)
+}
+
+/// Light from the environment map in one direction picked by how bright it
+/// is, weighted against the chance of the BSDF finding it instead
+fn from_environment(
+    hit: &Intersection,
+    bsdf: &Bsdf,
+    outgoing: &Vector3D,
+    scattered: f64,
+    scene: &Scene,
+    rng: &mut impl Rng,
+) -> LightColour {
+    let Some(environment) = &scene.environment else {
+        return LightColour::default();
+    };
+    let Some(sample) = environment.sample((rng.gen(), rng.gen())) else {
+        return LightColour::default();
+    };
+    let cos = sample.direction.dot(&hit.shading_normal());
+    if cos <= 0.0 {
+        return LightColour::default();
+    }
+    let shadow = LightSample {
+        direction: sample.direction,
+        distance: f64::INFINITY,
+        colour: LightColour::default(),
+    };
+    if scene.settings.shadows && hit.in_shadow(&shadow, scene) {
+        return LightColour::default();
+    }
+
+    let bsdf_pdf = scattered * bsdf.pdf(outgoing, &sample.direction);
+    let weight = power_heuristic(sample.pdf, bsdf_pdf);
+    bsdf.eval(outgoing, &sample.direction).mul(&sample.radiance)
+        * (scattered * cos * weight / sample.pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Camera, EnvironmentMap, LightSource, Material, Plane, Sphere};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn floor_scene() -> Scene {
+        let mut scene = Scene::new(Camera::default());
+        scene.remove_light(0);
+        scene.add_shape(Plane::new(
+            Point::new(0.0, 0.0, 0.0),
+            Vector3D::new(0.0, 1.0, 0.0),
+            Material::new(LightColour::new(1.0, 1.0, 1.0), 0.0),
+        ));
+        scene
+    }
+
+    fn ray_down() -> Ray {
+        Ray {
+            origin: Point::new(0.0, 100.0, 0.0),
+            direction: Vector3D::new(0.0, -1.0, 0.0),
+        }
+    }
+
+    fn mean_radiance(scene: &Scene, ray: &Ray, n: usize) -> LightColour {
+        let mut rng = StdRng::seed_from_u64(1);
+        (0..n).fold(LightColour::default(), |total, _| {
+            total + trace_path(ray, scene, &mut rng)
+        }) / n as f64
+    }
+
+    #[test]
+    fn point_light_matches_phong_diffuse() {
+        let mut scene = floor_scene();
+        scene.add_light(LightSource::point(
+            Point::new(0.0, 200.0, 0.0),
+            LightColour::new(0.5, 0.5, 0.5),
+        ));
+        // Nothing for light to bounce back off, so every path gives the same
+        let mut rng = StdRng::seed_from_u64(1);
+        let radiance = trace_path(&ray_down(), &scene, &mut rng);
+        assert!((radiance.x - 0.5).abs() < 1e-9, "{radiance:?}");
+    }
+
+    #[test]
+    fn emissive_sphere_lights_floor() {
+        let mut scene = floor_scene();
+        let (height, radius) = (500.0, 100.0);
+        let emission = LightColour::new(2.0, 2.0, 2.0);
+        scene.add_shape(Sphere::new(
+            Point::new(0.0, height, 0.0),
+            radius,
+            Material::new(LightColour::default(), 0.0).with_emission(emission),
+        ));
+        // A white floor directly under a sphere of radiance L reflects
+        // L * (r / d)^2 back up. The sphere is black so the light doesn't
+        // bounce between them
+        let expected = emission.x * (radius / height).powi(2);
+        let radiance = mean_radiance(&scene, &ray_down(), 4000);
+        assert!(
+            (radiance.x - expected).abs() < expected * 0.05,
+            "{} != {expected}",
+            radiance.x
+        );
+    }
+
+    #[test]
+    fn area_lights_use_the_given_rng() {
+        let mut scene = floor_scene();
+        scene.add_light(LightSource::rectangular(
+            Point::new(0.0, 200.0, 0.0),
+            Vector3D::new(100.0, 0.0, 0.0),
+            Vector3D::new(0.0, 0.0, 100.0),
+            2,
+            LightColour::new(1.0, 1.0, 1.0),
+        ));
+        let trace = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            trace_path(&ray_down(), &scene, &mut rng)
+        };
+        assert_eq!(trace(7), trace(7));
+    }
+
+    #[test]
+    fn unlit_scene_is_black() {
+        let scene = floor_scene();
+        let radiance = mean_radiance(&scene, &ray_down(), 10);
+        assert_eq!(radiance, LightColour::default());
+    }
+
+    #[test]
+    fn floor_under_uniform_sky_reflects_it() {
+        // Light from the whole sky scattered by a white diffuse floor gives
+        // back exactly the sky's radiance
+        let mut scene = floor_scene();
+        let sky = image::Rgb32FImage::from_pixel(8, 4, image::Rgb([0.7; 3]));
+        scene.environment =
+            Some(EnvironmentMap::new("").with_image(&sky.into()));
+        let radiance = mean_radiance(&scene, &ray_down(), 4000);
+        assert!((radiance.x - 0.7).abs() < 0.7 * 0.05, "{radiance:?}");
+    }
+}
@@ -0,0 +1,20 @@
+use crate::{HdrImage, ToneMapping};
+use gtk::gdk_pixbuf::Pixbuf;
+
+/// Tone map an HDR image into a `Pixbuf` so it can be displayed
+pub fn display(hdr: &HdrImage, img: &mut Pixbuf, tone_mapping: &ToneMapping) {
+    assert_eq!(
+        (hdr.width(), hdr.height()),
+        (img.width() as usize, img.height() as usize),
+        "Pixbuf must be the same size as the image being displayed"
+    );
+    let row_stride = img.rowstride() as usize;
+    // Unsafe because of Pixbuf.pixels(), should be fine though because the reason
+    // unsafe is because you can't have any other reads/writes to the pixbuf while
+    // the pixels() reference is still active, and because this is all taking
+    // place inside a function where Pixbuf has been passed as &mut reference,
+    // nothing else can read/write Pixbuf anyway.
+    unsafe {
+        hdr.quantise_into(img.pixels(), row_stride, tone_mapping);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{LightColour, Point};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::f64::consts::PI;
+
+/// How far marble's veins are pushed around by turbulence
+const MARBLE_DISTORTION: f64 = 1.5;
+
+/// How far wood's rings are pushed around by turbulence, in rings
+const WOOD_DISTORTION: f64 = 0.4;
+
+/// Gradients of Perlin noise at the lattice points, the middles of the edges
+/// of a cube
+const GRADIENTS: [[f64; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Shapes a procedural texture can make, each giving a value from 0.0 to
+/// 1.0 which picks a colour between the texture's two colours
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// Cubes alternating between the two colours
+    #[default]
+    Checker,
+    /// Smooth Perlin noise
+    Noise,
+    /// Noise at several scales added together, with sharp creases
+    Turbulence,
+    /// Bands bent into veins by turbulence
+    Marble,
+    /// Rings around the y axis, bent by turbulence
+    Wood,
+    /// Distance to the nearest of a random scattering of points, which
+    /// looks like cells or cracked stone
+    Cells,
+}
+
+/// A texture made up from a pattern through space, so it needs no image
+/// file and has no seams. It's evaluated at points in the scene rather than
+/// at texture coordinates, so shapes look carved out of it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProceduralTexture {
+    pattern: Pattern,
+    /// The colours the pattern goes between
+    #[serde(default = "default_colours")]
+    colours: [LightColour; 2],
+    /// Size of the pattern's features, in the same units as the scene
+    #[serde(default = "default_size", deserialize_with = "size")]
+    size: f64,
+    /// How many scales of noise are added together for turbulence, marble
+    /// and wood
+    #[serde(default = "default_octaves", deserialize_with = "octaves")]
+    octaves: u32,
+}
+
+fn default_colours() -> [LightColour; 2] {
+    [LightColour::default(), LightColour::new(1.0, 1.0, 1.0)]
+}
+
+fn default_size() -> f64 {
+    50.0
+}
+
+fn default_octaves() -> u32 {
+    6
+}
+
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let size = f64::deserialize(deserializer)?;
+    if size > 0.0 {
+        Ok(size)
+    } else {
+        Err(D::Error::custom(format!(
+            "size must be above 0, not {size}"
+        )))
+    }
+}
+
+fn octaves<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u32, D::Error> {
+    let octaves = u32::deserialize(deserializer)?;
+    if octaves > 0 {
+        Ok(octaves)
+    } else {
+        Err(D::Error::custom("there must be at least 1 octave"))
+    }
+}
+
+impl ProceduralTexture {
+    /// The pattern in black and white
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            colours: default_colours(),
+            size: default_size(),
+            octaves: default_octaves(),
+        }
+    }
+
+    pub fn with_colours(mut self, from: LightColour, to: LightColour) -> Self {
+        self.colours = [from, to];
+        self
+    }
+
+    /// Sizes are kept above zero
+    pub fn with_size(mut self, size: f64) -> Self {
+        self.size = size.max(f64::EPSILON);
+        self
+    }
+
+    /// At least one octave is always used
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves.max(1);
+        self
+    }
+
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Colour of the texture at `point`
+    pub fn sample(&self, point: &Point) -> LightColour {
+        let t = self.value(&(*point / self.size)).clamp(0.0, 1.0);
+        self.colours[0] * (1.0 - t) + self.colours[1] * t
+    }
+
+    /// The pattern from 0.0 to 1.0 at `p`, in units of the texture's size
+    fn value(&self, p: &Point) -> f64 {
+        match self.pattern {
+            Pattern::Checker => {
+                let sum = p.x.floor() + p.y.floor() + p.z.floor();
+                sum.rem_euclid(2.0)
+            }
+            Pattern::Noise => 0.5 * (perlin(p) + 1.0),
+            Pattern::Turbulence => turbulence(p, self.octaves),
+            Pattern::Marble => {
+                let phase =
+                    p.x + MARBLE_DISTORTION * turbulence(p, self.octaves);
+                0.5 * (1.0 + (PI * phase).sin())
+            }
+            Pattern::Wood => {
+                let rings = (p.x * p.x + p.z * p.z).sqrt()
+                    + WOOD_DISTORTION * turbulence(p, self.octaves);
+                rings.rem_euclid(1.0)
+            }
+            Pattern::Cells => worley(p),
+        }
+    }
+}
+
+/// Ken Perlin's improved gradient noise, which is zero at points with whole
+/// number coordinates and varies smoothly between about -1.0 and 1.0
+pub fn perlin(p: &Point) -> f64 {
+    let cell = [p.x.floor(), p.y.floor(), p.z.floor()];
+    let f = [p.x - cell[0], p.y - cell[1], p.z - cell[2]];
+    let corner = |dx: usize, dy: usize, dz: usize| {
+        let d = [dx as f64, dy as f64, dz as f64];
+        let h = hash([
+            cell[0] as i64 + dx as i64,
+            cell[1] as i64 + dy as i64,
+            cell[2] as i64 + dz as i64,
+        ]);
+        let g = GRADIENTS[(h % GRADIENTS.len() as u64) as usize];
+        (0..3).map(|i| g[i] * (f[i] - d[i])).sum::<f64>()
+    };
+    let [u, v, w] = f.map(fade);
+    let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+    let near = lerp(
+        v,
+        lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+        lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+    );
+    let far = lerp(
+        v,
+        lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+        lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+    );
+    lerp(w, near, far)
+}
+
+/// Fractal sum of the absolute value of `perlin` over `octaves` scales, each
+/// half the size and strength of the last, scaled to between 0.0 and 1.0. At
+/// least one octave is always used
+pub fn turbulence(p: &Point, octaves: u32) -> f64 {
+    let (mut total, mut strength, mut scale) = (0.0, 1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        total += strength * perlin(&(*p * scale)).abs();
+        strength *= 0.5;
+        scale *= 2.0;
+    }
+    // The strengths add up to just under 2
+    total / (2.0 - 2.0 * strength)
+}
+
+/// Worley noise: the distance from `p` to the nearest of a set of points
+/// scattered one to each unit cube, at most 1.0
+pub fn worley(p: &Point) -> f64 {
+    let cell = [p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64];
+    let mut nearest = f64::INFINITY;
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                let h = hash(neighbour);
+                let offset = |shift: u32| {
+                    ((h >> shift) & 0xFFFFF) as f64 / (1 << 20) as f64
+                };
+                let feature = Point::new(
+                    neighbour[0] as f64 + offset(0),
+                    neighbour[1] as f64 + offset(20),
+                    neighbour[2] as f64 + offset(40),
+                );
+                nearest = nearest.min((feature - *p).magnitude());
+            }
+        }
+    }
+    nearest.min(1.0)
+}
+
+/// Perlin's fade curve, which has zero first and second derivatives at 0.0
+/// and 1.0 so the noise is smooth across cells
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Pseudo-random bits for a lattice point, the same every time
+fn hash([x, y, z]: [i64; 3]) -> u64 {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    // The SplitMix64 finaliser, to spread every input bit over the output
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = Point> {
+        (0..1000).map(|i| {
+            let i = i as f64;
+            Point::new(i * 0.137, i * -0.291 + 3.0, (i * 0.05).sin() * 7.0)
+        })
+    }
+
+    #[test]
+    fn perlin_noise_is_bounded_and_zero_on_lattice() {
+        assert_eq!(perlin(&Point::new(3.0, -2.0, 7.0)), 0.0);
+        for p in points() {
+            let n = perlin(&p);
+            assert!(n.abs() <= 1.0 + 1e-9, "{n} at {p:?}");
+        }
+        // Not constant between the lattice points
+        let spread = points().map(|p| perlin(&p)).fold(0.0, f64::max);
+        assert!(spread > 0.3, "{spread}");
+    }
+
+    #[test]
+    fn noise_is_continuous_across_cells() {
+        let (below, above) = (0.999_999, 1.000_001);
+        let a = perlin(&Point::new(below, 0.3, 0.6));
+        let b = perlin(&Point::new(above, 0.3, 0.6));
+        assert!((a - b).abs() < 1e-4);
+        let a = worley(&Point::new(below, 0.3, 0.6));
+        let b = worley(&Point::new(above, 0.3, 0.6));
+        assert!((a - b).abs() < 1e-4);
+    }
+
+    #[test]
+    fn turbulence_always_has_an_octave() {
+        let p = Point::new(0.3, 0.6, 0.1);
+        assert_eq!(turbulence(&p, 0), turbulence(&p, 1));
+    }
+
+    #[test]
+    fn checker_alternates() {
+        let texture = ProceduralTexture::new(Pattern::Checker).with_size(10.0);
+        let white = LightColour::new(1.0, 1.0, 1.0);
+        assert_eq!(
+            texture.sample(&Point::new(5.0, 5.0, 5.0)),
+            LightColour::default()
+        );
+        assert_eq!(texture.sample(&Point::new(15.0, 5.0, 5.0)), white);
+        assert_eq!(texture.sample(&Point::new(-5.0, 5.0, 5.0)), white);
+        assert_eq!(
+            texture.sample(&Point::new(15.0, 15.0, 5.0)),
+            LightColour::default()
+        );
+    }
+
+    #[test]
+    fn patterns_stay_between_colours() {
+        let (from, to) = (
+            LightColour::new(0.2, 0.1, 0.0),
+            LightColour::new(0.8, 0.5, 0.3),
+        );
+        for pattern in [
+            Pattern::Noise,
+            Pattern::Turbulence,
+            Pattern::Marble,
+            Pattern::Wood,
+            Pattern::Cells,
+        ] {
+            let texture = ProceduralTexture::new(pattern)
+                .with_colours(from, to)
+                .with_size(2.0);
+            for p in points() {
+                let colour = texture.sample(&p);
+                assert!(
+                    colour.x >= from.x - 1e-9 && colour.x <= to.x + 1e-9,
+                    "{pattern:?} gave {colour:?}"
+                );
+            }
+        }
+    }
+}
@@ -1,89 +1,333 @@
-use crate::shapes::Shape;
 use crate::{
-    Camera, Intersection, Matrix3x3, PixelColour, Ray, Sphere, ARRAY_WIDTH,
-    BYTES_PER_PIXEL,
+    trace_path, AccumulationBuffer, HdrImage, LightColour, PixelSamples, Ray,
+    ReconstructionFilter, SamplePattern, Scene, ToneMapping,
 };
-use gtk::gdk_pixbuf::Pixbuf;
-use image::{ImageFormat, RgbaImage};
+use image::{ImageFormat, ImageResult};
+use rand::Rng;
 use rayon::prelude::*;
-use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
-const BACKGROUND: PixelColour = PixelColour { x: 0, y: 0, z: 0 };
-
-pub fn render(img: &mut Pixbuf, camera: &Camera, shapes: &Vec<Sphere>) {
-    let rotation_matrix = camera.general_rotation_matrix();
-    // Unsafe because of Pixbuf.pixels(), should be fine though because the reason
-    // unsafe is because you can't have any other reads/writes to the pixbuf while
-    // the pixels() reference is still active, and because this is all taking
-    // place inside a function where Pixbuf has been passed as &mut reference,
-    // nothing else can read/write Pixbuf anyway.
-    unsafe {
-        img.pixels()
-            .par_chunks_mut(ARRAY_WIDTH)
-            .enumerate()
-            .for_each(|(j, row)| {
-                row.chunks_mut(BYTES_PER_PIXEL).enumerate().for_each(
-                    |(i, px)| {
-                        let new_colour = calculate_pixel_colour(
-                            i,
-                            j,
-                            camera,
-                            &shapes,
-                            &rotation_matrix,
-                        );
-                        let new_colour =
-                            [new_colour.x, new_colour.y, new_colour.z, 255];
-                        if new_colour != px {
-                            px.copy_from_slice(&new_colour);
-                        }
-                    },
-                );
-            })
+const BACKGROUND: LightColour = LightColour {
+    x: 0.0,
+    y: 0.0,
+    z: 0.0,
+};
+
+const DEFAULT_MAX_DEPTH: usize = 4;
+
+/// How the light reaching the camera along each ray is worked out
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Phong shading from each light, plus mirror reflections and
+    /// refractions. Fast, but nothing is lit by light bouncing off other
+    /// shapes
+    #[default]
+    Phong,
+    /// Monte Carlo path tracing, which includes light bouncing between
+    /// diffuse surfaces and light given off by emissive shapes. Noisy until
+    /// plenty of samples have been averaged
+    PathTracer,
+}
+
+impl Integrator {
+    pub const ALL: [Self; 2] = [Self::Phong, Self::PathTracer];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Phong => "Phong",
+            Self::PathTracer => "Path tracer",
+        }
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    /// Parse an integrator from its lowercase name, e.g. "path_tracer"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phong" => Ok(Self::Phong),
+            "path_tracer" => Ok(Self::PathTracer),
+            _ => Err(format!(
+                "unknown integrator '{s}', expected phong or path_tracer"
+            )),
+        }
+    }
+}
+
+/// Options which trade render quality against speed
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Cast shadow rays towards each light to check whether it's blocked
+    pub shadows: bool,
+    /// How many times a ray can bounce off reflective surfaces
+    pub max_depth: usize,
+    /// Rays traced through each pixel and averaged to smooth out jagged
+    /// edges. A single sample goes through the centre of the pixel
+    pub samples_per_pixel: usize,
+    /// Where in the pixel samples are taken when there's more than one
+    pub sample_pattern: SamplePattern,
+    /// How samples are weighted when averaging them
+    pub filter: ReconstructionFilter,
+    pub integrator: Integrator,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            shadows: true,
+            max_depth: DEFAULT_MAX_DEPTH,
+            samples_per_pixel: 1,
+            sample_pattern: SamplePattern::default(),
+            filter: ReconstructionFilter::default(),
+            integrator: Integrator::default(),
+        }
     }
 }
 
-pub fn write_img(img: &RgbaImage, path: &Path) {
-    img.save_with_format(path, ImageFormat::Png)
-        .expect("It's all gone wrong");
+/// Shared flag for stopping a render part way through from another thread.
+/// Clones all share the same flag
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask any render using this token to stop as soon as it can
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Trace every pixel of the scene into an HDR image. The image is resized to
+/// the camera's resolution first if it doesn't already match
+pub fn render(img: &mut HdrImage, scene: &Scene) {
+    render_with_progress(img, scene, |_, _| {});
 }
 
-fn calculate_pixel_colour(
+/// Same as `render`, calling `progress` with the number of rows finished so
+/// far and the total number of rows each time a row is completed. Rows are
+/// rendered in parallel, so `progress` may be called from any thread
+pub fn render_with_progress(
+    img: &mut HdrImage,
+    scene: &Scene,
+    progress: impl Fn(usize, usize) + Sync,
+) {
+    let camera = scene.camera();
+    let (width, height) = (camera.img_width(), camera.img_height());
+    if img.width() != width || img.height() != height {
+        img.resize(width, height);
+    }
+    let total_rows = img.height();
+    let rows_done = AtomicUsize::new(0);
+    img.par_rows_mut().enumerate().for_each(|(j, row)| {
+        row.iter_mut().enumerate().for_each(|(i, px)| {
+            *px = calculate_pixel_colour(i, j, scene);
+        });
+        progress(rows_done.fetch_add(1, Ordering::Relaxed) + 1, total_rows);
+    });
+}
+
+/// Add another pass of samples to `accumulation`, refining the image from
+/// the passes before it. The buffer is cleared first if it isn't the
+/// camera's resolution. The first pass gives the same image as `render`, and
+/// later passes sample different points in each pixel
+pub fn render_pass(accumulation: &mut AccumulationBuffer, scene: &Scene) {
+    render_pass_cancellable(accumulation, scene, &CancelToken::new());
+}
+
+/// Same as `render_pass`, but giving up early if `cancel` is cancelled.
+/// Returns whether the pass was finished. If it wasn't, `accumulation` only
+/// has part of a pass in it and should be cleared before it's used again
+pub fn render_pass_cancellable(
+    accumulation: &mut AccumulationBuffer,
+    scene: &Scene,
+    cancel: &CancelToken,
+) -> bool {
+    let camera = scene.camera();
+    let (width, height) = (camera.img_width(), camera.img_height());
+    if accumulation.width() != width || accumulation.height() != height {
+        accumulation.resize(width, height);
+    }
+    let pass = accumulation.passes();
+    accumulation.add_pass(|i, j| {
+        // Skip the rest of the pixels rather than stopping the pass, which
+        // is cheap enough once nothing is being traced
+        if cancel.is_cancelled() {
+            PixelSamples::default()
+        } else {
+            pixel_samples(i, j, scene, pass)
+        }
+    });
+    !cancel.is_cancelled()
+}
+
+/// Tone map an HDR image and save it as a PNG
+pub fn write_img(
+    img: &HdrImage,
+    tone_mapping: &ToneMapping,
+    path: &Path,
+) -> ImageResult<()> {
+    img.to_rgba_image(tone_mapping)
+        .save_with_format(path, ImageFormat::Png)
+}
+
+fn calculate_pixel_colour(i: usize, j: usize, scene: &Scene) -> LightColour {
+    pixel_samples(i, j, scene, 0).colour()
+}
+
+/// Trace the samples for one pixel in the given pass. With a single sample
+/// per pixel, the first pass goes through the centre of the pixel and later
+/// ones through random points, so that repeated passes cover all of it
+fn pixel_samples(
     i: usize,
     j: usize,
-    camera: &Camera,
-    shapes: &Vec<Sphere>,
-    rotation_matrix: &Matrix3x3<f64>,
-) -> PixelColour {
-    let (origin, direction) = camera.pixel_props(i, j, rotation_matrix);
-    let ray = Ray { origin, direction };
-    let intersections = shapes
-        .iter()
-        .map(|s| s.intersection(&ray, &camera))
-        .collect::<Vec<_>>();
-    let closest_intersect = closest_intersect(intersections);
-    if let Some(intersection) = closest_intersect {
-        intersection.phong(&origin, camera.ambient_coefficient())
-    } else {
-        BACKGROUND
+    scene: &Scene,
+    pass: usize,
+) -> PixelSamples {
+    let camera = scene.camera();
+    let settings = &scene.settings;
+    let samples = settings.samples_per_pixel;
+    let mut rng = rand::thread_rng();
+    let points = match (samples, pass) {
+        (0 | 1, 0) => vec![(0.5, 0.5)],
+        (0 | 1, _) => SamplePattern::Random.points(1, &mut rng),
+        _ => settings.sample_pattern.points(samples, &mut rng),
+    };
+
+    // Samples are spread over the whole filter, which can reach into the
+    // neighbouring pixels
+    let filter = settings.filter;
+    let width = filter.radius() * 2.0;
+    points
+        .into_iter()
+        .fold(PixelSamples::default(), |samples, (u, v)| {
+            let (dx, dy) = ((u - 0.5) * width, (v - 0.5) * width);
+            let weight = filter.weight(dx, dy);
+            if weight == 0.0 {
+                return samples;
+            }
+            let (origin, direction) =
+                camera.sample_props(i as f64 + dx, j as f64 + dy);
+            let ray = Ray { origin, direction };
+            samples
+                + PixelSamples {
+                    total: radiance(&ray, scene, &mut rng) * weight,
+                    weight,
+                }
+        })
+}
+
+/// Radiance seen along a camera ray, using the scene's integrator
+fn radiance(ray: &Ray, scene: &Scene, rng: &mut impl Rng) -> LightColour {
+    match scene.settings.integrator {
+        Integrator::Phong => trace(ray, scene, 0, rng),
+        Integrator::PathTracer => trace_path(ray, scene, rng),
     }
 }
 
-fn closest_intersect(
-    intersects: Vec<Option<Intersection>>,
-) -> Option<Intersection> {
-    let mut closest: Option<Intersection> = None;
-    for intersection_option in intersects {
-        if let Some(intersection) = intersection_option {
-            if let Some(closest_intersection) = closest {
-                match intersection.t().total_cmp(&closest_intersection.t()) {
-                    Ordering::Less => closest = Some(intersection),
-                    _ => {}
+/// Radiance seen along a ray, following reflections and refractions until
+/// `depth` reaches the scene's maximum depth
+fn trace(
+    ray: &Ray,
+    scene: &Scene,
+    depth: usize,
+    rng: &mut impl Rng,
+) -> LightColour {
+    if let Some(intersection) = scene.closest_intersect(ray) {
+        let material = intersection.object().material();
+        let colour =
+            intersection.phong(&ray.origin, scene, rng) + material.emission();
+        let reflectivity = material.reflectivity();
+        let transparency = material.transparency();
+        if depth >= scene.settings.max_depth
+            || (reflectivity <= 0.0 && transparency <= 0.0)
+        {
+            return colour;
+        }
+
+        let reflected =
+            trace(&intersection.reflected_ray(), scene, depth + 1, rng);
+        let mut total = (colour * (1.0 - reflectivity)
+            + reflected * reflectivity)
+            * (1.0 - transparency);
+        if transparency > 0.0 {
+            // Light passing through the surface is split between reflecting
+            // off it and refracting through it depending on the angle
+            let fresnel = intersection.fresnel();
+            let refracted = match intersection.refracted_ray() {
+                Some(refracted_ray) => {
+                    trace(&refracted_ray, scene, depth + 1, rng)
                 }
-            } else {
-                closest = Some(intersection);
-            }
+                None => reflected,
+            };
+            total = total
+                + (reflected * fresnel + refracted * (1.0 - fresnel))
+                    * transparency;
         }
+        total
+    } else {
+        background(ray, scene)
+    }
+}
+
+/// Radiance seen along a ray which misses every shape
+fn background(ray: &Ray, scene: &Scene) -> LightColour {
+    scene
+        .environment
+        .as_ref()
+        .map_or(BACKGROUND, |environment| {
+            environment.radiance(&ray.direction)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Camera;
+
+    #[test]
+    fn first_pass_matches_render() {
+        let mut camera = Camera::default();
+        camera.set_resolution(24, 16);
+        let scene = Scene::demo(camera);
+        let mut rendered = HdrImage::new(0, 0);
+        render(&mut rendered, &scene);
+
+        let mut accumulation = AccumulationBuffer::new(0, 0);
+        render_pass(&mut accumulation, &scene);
+        let mut accumulated = HdrImage::new(0, 0);
+        accumulation.resolve_into(&mut accumulated);
+        assert_eq!(accumulated.pixels(), rendered.pixels());
+
+        render_pass(&mut accumulation, &scene);
+        assert_eq!(accumulation.passes(), 2);
+    }
+
+    #[test]
+    fn cancelled_pass_reports_unfinished() {
+        let scene = Scene::demo(Camera::default());
+        let mut accumulation = AccumulationBuffer::new(0, 0);
+        let cancel = CancelToken::new();
+        cancel.clone().cancel();
+        assert!(cancel.is_cancelled());
+        assert!(!render_pass_cancellable(&mut accumulation, &scene, &cancel));
+        let mut hdr = HdrImage::new(0, 0);
+        accumulation.resolve_into(&mut hdr);
+        assert!(hdr.pixels().iter().all(|px| *px == LightColour::default()));
     }
-    closest
 }
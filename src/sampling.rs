@@ -0,0 +1,379 @@
+use crate::Vector3D;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{PI, TAU};
+use std::str::FromStr;
+
+/// How the samples for a pixel are spread over it
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplePattern {
+    /// Independent random points, which can clump together
+    Random,
+    /// The centres of a regular grid of cells. Best with square numbers of
+    /// samples
+    Stratified,
+    /// A random point in each cell of a regular grid, which keeps the even
+    /// coverage of `Stratified` without its regular aliasing
+    #[default]
+    Jittered,
+    /// The Halton sequence in bases 2 and 3, randomly shifted for each pixel
+    Halton,
+    /// The first two dimensions of the Sobol sequence, randomly shifted for
+    /// each pixel
+    Sobol,
+}
+
+impl SamplePattern {
+    pub const ALL: [Self; 5] = [
+        Self::Random,
+        Self::Stratified,
+        Self::Jittered,
+        Self::Halton,
+        Self::Sobol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Random => "Random",
+            Self::Stratified => "Stratified",
+            Self::Jittered => "Jittered",
+            Self::Halton => "Halton",
+            Self::Sobol => "Sobol",
+        }
+    }
+
+    /// `n` points inside the unit square, `[0, 1)` on both axes
+    pub fn points(&self, n: usize, rng: &mut impl Rng) -> Vec<(f64, f64)> {
+        match self {
+            Self::Random => (0..n).map(|_| (rng.gen(), rng.gen())).collect(),
+            Self::Stratified => grid(n, || (0.5, 0.5)),
+            Self::Jittered => grid(n, || (rng.gen(), rng.gen())),
+            Self::Halton => shifted(n, rng, |k| {
+                (radical_inverse(k, 2), radical_inverse(k, 3))
+            }),
+            Self::Sobol => shifted(n, rng, |k| (sobol(k, 0), sobol(k, 1))),
+        }
+    }
+}
+
+impl FromStr for SamplePattern {
+    type Err = String;
+
+    /// Parse a pattern from its lowercase name, e.g. "halton"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Self::Random),
+            "stratified" => Ok(Self::Stratified),
+            "jittered" => Ok(Self::Jittered),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            _ => Err(format!(
+                "unknown sample pattern '{s}', expected one of random, \
+                 stratified, jittered, halton or sobol"
+            )),
+        }
+    }
+}
+
+/// Weighting given to each sample depending on how far it is from the centre
+/// of the pixel, when averaging them into the pixel's colour
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconstructionFilter {
+    /// Every sample inside the pixel counts the same
+    #[default]
+    Box,
+    /// Weight falling off in a straight line to zero one pixel away
+    Tent,
+    /// A Gaussian bell curve, giving soft edges
+    Gaussian,
+    /// The Mitchell-Netravali cubic, which keeps edges sharper than a
+    /// Gaussian at the cost of slight ringing around them
+    Mitchell,
+}
+
+impl ReconstructionFilter {
+    pub const ALL: [Self; 4] =
+        [Self::Box, Self::Tent, Self::Gaussian, Self::Mitchell];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Box => "Box",
+            Self::Tent => "Tent",
+            Self::Gaussian => "Gaussian",
+            Self::Mitchell => "Mitchell",
+        }
+    }
+
+    /// How far from the centre of a pixel, in pixels, samples still have
+    /// any weight. Anything wider than half a pixel blends in some of the
+    /// neighbouring pixels
+    pub fn radius(&self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => GAUSSIAN_RADIUS,
+            Self::Mitchell => 2.0,
+        }
+    }
+
+    /// Weight of a sample `dx` and `dy` pixels from the centre of the pixel.
+    /// Mitchell weights can be negative
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+        match self {
+            Self::Box => 1.0,
+            Self::Tent => 1.0 - x,
+            Self::Gaussian => {
+                // Shifted down so it reaches zero at the radius rather than
+                // being cut off suddenly
+                let gaussian = |x: f64| {
+                    (-x * x / (2.0 * GAUSSIAN_SIGMA * GAUSSIAN_SIGMA)).exp()
+                };
+                gaussian(x) - gaussian(GAUSSIAN_RADIUS)
+            }
+            Self::Mitchell => {
+                const B: f64 = 1.0 / 3.0;
+                const C: f64 = 1.0 / 3.0;
+                let (x2, x3) = (x * x, x * x * x);
+                let weight = if x < 1.0 {
+                    (12.0 - 9.0 * B - 6.0 * C) * x3
+                        + (-18.0 + 12.0 * B + 6.0 * C) * x2
+                        + (6.0 - 2.0 * B)
+                } else {
+                    (-B - 6.0 * C) * x3
+                        + (6.0 * B + 30.0 * C) * x2
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C)
+                };
+                weight / 6.0
+            }
+        }
+    }
+}
+
+impl FromStr for ReconstructionFilter {
+    type Err = String;
+
+    /// Parse a filter from its lowercase name, e.g. "mitchell"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Self::Box),
+            "tent" => Ok(Self::Tent),
+            "gaussian" => Ok(Self::Gaussian),
+            "mitchell" => Ok(Self::Mitchell),
+            _ => Err(format!(
+                "unknown filter '{s}', expected one of box, tent, gaussian \
+                 or mitchell"
+            )),
+        }
+    }
+}
+
+/// Direction in the hemisphere around `normal`, more likely the closer it is
+/// to the normal, with probability density `cos(angle to normal) / π`. `u` is
+/// a pair of uniform random numbers in `[0, 1)`
+pub fn cosine_hemisphere(normal: &Vector3D, u: (f64, f64)) -> Vector3D {
+    // Pick a point on a disk and project it up onto the hemisphere
+    let (r, phi) = (u.0.sqrt(), TAU * u.1);
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    tangent * (r * phi.cos())
+        + bitangent * (r * phi.sin())
+        + *normal * (1.0 - u.0).max(0.0).sqrt()
+}
+
+/// Probability density of `cosine_hemisphere` picking a direction whose
+/// cosine with the normal is `cos`
+pub fn cosine_hemisphere_pdf(cos: f64) -> f64 {
+    cos.max(0.0) / PI
+}
+
+/// Veach's power heuristic, the weight given to a sample from a strategy
+/// with probability density `pdf` when another strategy could have produced
+/// it with density `other_pdf`
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+const GAUSSIAN_SIGMA: f64 = 0.5;
+const GAUSSIAN_RADIUS: f64 = 1.5;
+
+/// One point in each of `n` cells covering the square, a row at a time.
+/// When `n` isn't a square number some rows get an extra column, so no cells
+/// are left empty. `offset` gives the point's position in its cell
+fn grid(n: usize, mut offset: impl FnMut() -> (f64, f64)) -> Vec<(f64, f64)> {
+    let rows = ((n as f64).sqrt() as usize).max(1);
+    (0..rows)
+        .flat_map(|row| {
+            let columns = n / rows + usize::from(row < n % rows);
+            (0..columns).map(move |column| (row, column, columns))
+        })
+        .map(|(row, column, columns)| {
+            let (x, y) = offset();
+            (
+                (column as f64 + x) / columns as f64,
+                (row as f64 + y) / rows as f64,
+            )
+        })
+        .collect()
+}
+
+/// The first `n` points of a low discrepancy sequence, all moved by the same
+/// random amount and wrapped around the square. Without the shift every
+/// pixel would use exactly the same points
+fn shifted(
+    n: usize,
+    rng: &mut impl Rng,
+    sequence: impl Fn(u32) -> (f64, f64),
+) -> Vec<(f64, f64)> {
+    let (shift_x, shift_y): (f64, f64) = (rng.gen(), rng.gen());
+    (0..n as u32)
+        .map(|k| {
+            let (x, y) = sequence(k);
+            ((x + shift_x).fract(), (y + shift_y).fract())
+        })
+        .collect()
+}
+
+/// Mirror the digits of `k` in `base` around the decimal point, so 1, 2, 3
+/// in base 2 become 0.1, 0.01, 0.11
+fn radical_inverse(mut k: u32, base: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    while k > 0 {
+        result += (k % base) as f64 * scale;
+        k /= base;
+        scale *= inverse_base;
+    }
+    result
+}
+
+/// Point `k` of dimension 0 or 1 of the Sobol sequence
+fn sobol(k: u32, dimension: usize) -> f64 {
+    let mut result = 0u32;
+    // Dimension 0 is the identity generator matrix, which reverses the bits
+    // of `k`, and dimension 1 uses direction numbers from Pascal's triangle
+    let mut direction = 1u32 << 31;
+    let mut k = k;
+    while k > 0 {
+        if k & 1 == 1 {
+            result ^= direction;
+        }
+        direction = match dimension {
+            0 => direction >> 1,
+            _ => direction ^ (direction >> 1),
+        };
+        k >>= 1;
+    }
+    result as f64 / (1u64 << 32) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn points_stay_in_unit_square() {
+        let mut rng = rand::thread_rng();
+        for pattern in SamplePattern::ALL {
+            for n in [1, 2, 5, 16] {
+                let points = pattern.points(n, &mut rng);
+                assert_eq!(points.len(), n);
+                for (x, y) in points {
+                    assert!((0.0..1.0).contains(&x), "{pattern:?} {x}");
+                    assert!((0.0..1.0).contains(&y), "{pattern:?} {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_covers_each_cell() {
+        let points =
+            SamplePattern::Stratified.points(4, &mut rand::thread_rng());
+        assert_eq!(
+            points,
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+    }
+
+    #[test]
+    fn jittered_fills_every_cell_when_not_square() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let points = SamplePattern::Jittered.points(5, &mut rng);
+        // Three cells in the top row and two in the bottom one
+        let cell = |(x, y): (f64, f64)| {
+            if y < 0.5 {
+                (0, (x * 3.0) as usize)
+            } else {
+                (1, (x * 2.0) as usize)
+            }
+        };
+        let mut cells: Vec<_> = points.into_iter().map(cell).collect();
+        cells.sort();
+        assert_eq!(cells, vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn low_discrepancy_sequences() {
+        let halton: Vec<_> = (0..4).map(|k| radical_inverse(k, 3)).collect();
+        assert_eq!(halton, vec![0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0]);
+        let sobol_x: Vec<_> = (0..4).map(|k| sobol(k, 0)).collect();
+        assert_eq!(sobol_x, vec![0.0, 0.5, 0.25, 0.75]);
+        let sobol_y: Vec<_> = (0..4).map(|k| sobol(k, 1)).collect();
+        assert_eq!(sobol_y, vec![0.0, 0.5, 0.75, 0.25]);
+    }
+
+    #[test]
+    fn filters_peak_at_centre_and_vanish_outside_radius() {
+        for filter in ReconstructionFilter::ALL {
+            let centre = filter.weight(0.0, 0.0);
+            assert!(centre > 0.0, "{filter:?}");
+            assert!(filter.weight(0.3, 0.1) <= centre, "{filter:?}");
+            let outside = filter.radius() + 0.01;
+            assert_eq!(filter.weight(outside, 0.0), 0.0, "{filter:?}");
+            assert_eq!(filter.weight(0.0, -outside), 0.0, "{filter:?}");
+        }
+        // Mitchell's negative lobe sharpens edges
+        assert!(ReconstructionFilter::Mitchell.weight(1.5, 0.0) < 0.0);
+    }
+
+    #[test]
+    fn cosine_hemisphere_stays_above_surface() {
+        let mut rng = rand::thread_rng();
+        let mut normal = Vector3D::new(1.0, 2.0, -0.5);
+        normal.normalise();
+        let mut mean_cos = 0.0;
+        for _ in 0..2000 {
+            let direction = cosine_hemisphere(&normal, (rng.gen(), rng.gen()));
+            assert!((direction.magnitude() - 1.0).abs() < 1e-9);
+            let cos = direction.dot(&normal);
+            assert!(cos >= 0.0);
+            mean_cos += cos / 2000.0;
+        }
+        // The mean of cos over a cosine weighted hemisphere is 2/3
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.03, "{mean_cos}");
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    Bvh, Camera, EnvironmentMap, Intersection, LightSource, Point, Ray,
+    RenderSettings, Shape, Sphere, BURNT_ORANGE, ZIMA_BLUE,
+};
+use std::sync::OnceLock;
+
+/// Everything needed to render a frame: the shapes in the scene, the lights
+/// illuminating them and the camera they're viewed through
+#[derive(Clone)]
+pub struct Scene {
+    shapes: Vec<Box<dyn Shape>>,
+    pub lights: Vec<LightSource>,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+    /// Light from all around the scene, seen wherever rays miss every shape
+    pub environment: Option<EnvironmentMap>,
+    /// Built the first time the scene is rendered, and thrown away whenever
+    /// the shapes are changed
+    accelerator: OnceLock<Accelerator>,
+}
+
+/// BVH over the bounded shapes in a scene, plus the shapes which can't be put
+/// in one because they go on forever (e.g. planes)
+#[derive(Clone)]
+struct Accelerator {
+    bvh: Bvh,
+    unbounded: Vec<usize>,
+    /// Shapes which give off light, for the path tracer to sample
+    emitters: Vec<usize>,
+}
+
+impl Accelerator {
+    fn new(shapes: &[Box<dyn Shape>]) -> Self {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        let mut emitters = vec![];
+        for (i, shape) in shapes.iter().enumerate() {
+            if shape.material().is_emissive() {
+                emitters.push(i);
+            }
+            let aabb = shape.bounding_box();
+            if aabb.is_bounded() {
+                bounded.push((i, aabb));
+            } else {
+                unbounded.push(i);
+            }
+        }
+        Self {
+            bvh: Bvh::new(bounded),
+            unbounded,
+            emitters,
+        }
+    }
+}
+
+impl Scene {
+    /// Create an empty `Scene` lit by a single default `LightSource`
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            shapes: vec![],
+            lights: vec![LightSource::default()],
+            camera,
+            settings: RenderSettings::default(),
+            environment: None,
+            accelerator: OnceLock::new(),
+        }
+    }
+
+    pub fn with_shapes(camera: Camera, shapes: Vec<Box<dyn Shape>>) -> Self {
+        let mut scene = Self::new(camera);
+        scene.shapes = shapes;
+        scene
+    }
+
+    /// A handful of spheres around the origin, for trying things out
+    pub fn demo(camera: Camera) -> Self {
+        let shapes: Vec<Box<dyn Shape>> = vec![
+            Box::new(Sphere::default()),
+            Box::new(Sphere::default_with_pos(Point::new(100.0, 100.0, 200.0))),
+            Box::new(Sphere::default_with_pos(Point::new(200.0, 200.0, 400.0))),
+            Box::new(Sphere::new_with_colour(
+                Point::new(-150.0, -50.0, 200.0),
+                50.0,
+                ZIMA_BLUE,
+            )),
+            Box::new(Sphere::new_with_colour(
+                Point::new(34.0, 100.0, -150.0),
+                50.0,
+                BURNT_ORANGE,
+            )),
+        ];
+        Self::with_shapes(camera, shapes)
+    }
+
+    pub fn add_shape(&mut self, shape: impl Shape + 'static) {
+        self.shapes.push(Box::new(shape));
+        self.accelerator = OnceLock::new();
+    }
+
+    pub fn shapes(&self) -> &[Box<dyn Shape>] {
+        &self.shapes
+    }
+
+    pub fn shape(&self, index: usize) -> &dyn Shape {
+        self.shapes[index].as_ref()
+    }
+
+    /// Mutable access to a shape. The scene's BVH will be rebuilt the next
+    /// time it's rendered in case the shape has moved or changed size
+    pub fn shape_mut(&mut self, index: usize) -> &mut dyn Shape {
+        self.accelerator = OnceLock::new();
+        self.shapes[index].as_mut()
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn lights(&self) -> &[LightSource] {
+        &self.lights
+    }
+
+    pub fn add_light(&mut self, light: LightSource) {
+        self.lights.push(light);
+    }
+
+    pub fn remove_light(&mut self, index: usize) -> LightSource {
+        self.lights.remove(index)
+    }
+
+    /// Indices of the shapes which give off light themselves
+    pub fn emitters(&self) -> &[usize] {
+        &self.accelerator().emitters
+    }
+
+    /// Find the intersection between a ray and the shapes in the scene which
+    /// is closest to the origin of the ray, if there is one
+    pub fn closest_intersect<'a>(
+        &'a self,
+        ray: &'a Ray,
+    ) -> Option<Intersection<'a>> {
+        let accelerator = self.accelerator();
+        let bounded = accelerator
+            .bvh
+            .closest_intersect(ray, |i| self.shapes[i].intersection(ray));
+        accelerator
+            .unbounded
+            .iter()
+            .filter_map(|&i| self.shapes[i].intersection(ray))
+            .chain(bounded)
+            .min_by(|a, b| a.t().total_cmp(&b.t()))
+    }
+
+    fn accelerator(&self) -> &Accelerator {
+        self.accelerator
+            .get_or_init(|| Accelerator::new(&self.shapes))
+    }
+
+    /// Whether a ray hits any shape less than `distance` along it
+    pub fn is_occluded(&self, ray: &Ray, distance: f64) -> bool {
+        let blocks = |i: usize| {
+            self.shapes[i]
+                .intersection(ray)
+                .is_some_and(|intersection| intersection.t() < distance)
+        };
+        let accelerator = self.accelerator();
+        accelerator.unbounded.iter().any(|&i| blocks(i))
+            || accelerator.bvh.any_hit(ray, distance, blocks)
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new(Camera::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CameraParams, Vector3D};
+
+    fn test_scene() -> Scene {
+        let camera = Camera::new(CameraParams {
+            img_height: 10,
+            img_width: 10,
+            ..CameraParams::default()
+        });
+        let mut scene = Scene::new(camera);
+        scene.add_shape(Sphere::default_with_pos(Point::new(0.0, 0.0, 0.0)));
+        scene
+    }
+
+    fn ray_along_z() -> Ray {
+        Ray {
+            origin: Point::new(0.0, 0.0, -500.0),
+            direction: Vector3D::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    #[test]
+    fn sphere_in_the_way_occludes() {
+        assert!(test_scene().is_occluded(&ray_along_z(), 1000.0));
+    }
+
+    #[test]
+    fn sphere_beyond_distance_does_not_occlude() {
+        assert!(!test_scene().is_occluded(&ray_along_z(), 300.0));
+    }
+
+    #[test]
+    fn moved_sphere_does_not_occlude() {
+        let mut scene = test_scene();
+        assert!(scene.is_occluded(&ray_along_z(), 1000.0));
+        scene.shape_mut(0).set_position(Point::new(0.0, 500.0, 0.0));
+        assert!(!scene.is_occluded(&ray_along_z(), 1000.0));
+    }
+}
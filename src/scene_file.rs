@@ -0,0 +1,770 @@
+use crate::{
+    Camera, CameraParams, Disk, EnvironmentMap, LightSource, Material, Mesh,
+    ObjError, Plane, Point, RenderSettings, Scene, Shape, Sphere, Triangle,
+    Vector3D,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use toml::Spanned;
+
+/// The contents of a TOML scene file, e.g.
+///
+/// ```toml
+/// [camera]
+/// position = [0.0, 0.0, -1000.0]
+/// target = [0.0, 0.0, 0.0]
+/// width = 1920
+/// height = 1080
+///
+/// [materials.glass]
+/// colour = [0.9, 0.95, 1.0]
+/// transparency = 0.9
+/// refractive_index = 1.5
+///
+/// [[lights]]
+/// type = "point"
+/// position = [-500.0, -350.0, -350.0]
+///
+/// [[shapes]]
+/// type = "sphere"
+/// center = [0.0, 0.0, 0.0]
+/// radius = 100.0
+/// material = "glass"
+/// ```
+///
+/// Every section is optional, and a scene without any `[[lights]]` gets a
+/// single default one like `Scene::new`, unless it has `lights = []`.
+/// Materials can either be defined once under `[materials]` and referred to
+/// by name, or written out in full on a shape. An `[environment]` image with
+/// a `path` surrounds the scene
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SceneFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    camera: Option<Spanned<CameraParams>>,
+    settings: RenderSettings,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    materials: BTreeMap<String, Spanned<Material>>,
+    /// `None` when the file doesn't mention lights at all
+    lights: Option<Vec<LightSource>>,
+    shapes: Vec<Spanned<ShapeDescription>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<Spanned<EnvironmentMap>>,
+}
+
+/// A shape as it's written in a scene file, picked with its `type`, e.g.
+/// `type = "sphere"`. Shapes without a material use `Material::default()`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDescription {
+    Sphere {
+        center: Point,
+        radius: f64,
+        material: Option<MaterialRef>,
+    },
+    Plane {
+        point: Point,
+        normal: Vector3D,
+        material: Option<MaterialRef>,
+    },
+    Disk {
+        center: Point,
+        normal: Vector3D,
+        radius: f64,
+        material: Option<MaterialRef>,
+    },
+    Triangle {
+        vertices: [Point; 3],
+        normals: Option<[Vector3D; 3]>,
+        material: Option<MaterialRef>,
+    },
+    /// A Wavefront `.obj` file, relative to the scene file. It's moved so
+    /// its center is at `position` and scaled so its furthest vertex is
+    /// `size` from the center, if they're given. Setting `material` replaces
+    /// any materials from the file
+    Mesh {
+        obj: PathBuf,
+        position: Option<Point>,
+        size: Option<f64>,
+        material: Option<MaterialRef>,
+    },
+}
+
+/// Either the name of a material under `[materials]`, or a whole material
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialRef {
+    Named(String),
+    Inline(Box<Material>),
+}
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(std::io::Error),
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// The scene contains something which can't be written to a file
+    Unsupported(String),
+}
+
+impl Display for SceneFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneFileError::Io(e) => write!(f, "{e}"),
+            SceneFileError::Parse { line, message } => {
+                write!(f, "line {line}: {message}")
+            }
+            SceneFileError::Unsupported(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+impl From<std::io::Error> for SceneFileError {
+    fn from(value: std::io::Error) -> Self {
+        SceneFileError::Io(value)
+    }
+}
+
+impl Scene {
+    /// Load a scene from a TOML file. Any `.obj` files, texture images and
+    /// environment maps it references are loaded relative to the scene file
+    pub fn load(path: &Path) -> Result<Self, SceneFileError> {
+        let source = std::fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::from_toml(&source, base_dir)
+    }
+
+    /// Parse the contents of a TOML scene file, loading `.obj` files, texture
+    /// images and environment maps relative to `base_dir`. Their paths in the
+    /// scene have `base_dir` added, so they no longer depend on where the
+    /// scene file is
+    pub fn from_toml(
+        source: &str,
+        base_dir: &Path,
+    ) -> Result<Self, SceneFileError> {
+        let parse_error = |span: Option<Range<usize>>, message: String| {
+            SceneFileError::Parse {
+                line: span.map_or(1, |span| line_number(source, span.start)),
+                message,
+            }
+        };
+        let mut file: SceneFile = toml::from_str(source)
+            .map_err(|e| parse_error(e.span(), e.message().to_string()))?;
+        for material in file.materials.values_mut() {
+            let span = material.span();
+            load_textures(material.get_mut(), base_dir)
+                .map_err(|message| parse_error(Some(span), message))?;
+        }
+
+        let shapes = file
+            .shapes
+            .into_iter()
+            .map(|shape| {
+                let span = shape.span();
+                build_shape(shape.into_inner(), &file.materials, base_dir)
+                    .map_err(|message| parse_error(Some(span), message))
+            })
+            .collect::<Result<_, _>>()?;
+        let environment = file
+            .environment
+            .map(|environment| {
+                let span = environment.span();
+                let mut environment = environment.into_inner();
+                environment.load(base_dir).map_err(|e| {
+                    let path = environment.path().display();
+                    parse_error(
+                        Some(span),
+                        format!("couldn't load {path}: {e}"),
+                    )
+                })?;
+                environment.set_path(base_dir.join(environment.path()));
+                Ok::<_, SceneFileError>(environment)
+            })
+            .transpose()?;
+
+        let camera = match file.camera {
+            Some(camera) => {
+                let span = camera.span();
+                let camera = camera.into_inner();
+                camera
+                    .validate()
+                    .map_err(|message| parse_error(Some(span), message))?;
+                camera
+            }
+            None => CameraParams::default(),
+        };
+        let mut scene = Scene::with_shapes(Camera::new(camera), shapes);
+        scene.settings = file.settings;
+        scene.environment = environment;
+        if let Some(lights) = file.lights {
+            scene.lights = lights;
+        }
+        Ok(scene)
+    }
+
+    /// Save the scene as a TOML file which `Scene::load` can read back in.
+    /// Relative paths to `.obj` files and images are rewritten to be
+    /// relative to the new file
+    pub fn save(&self, path: &Path) -> Result<(), SceneFileError> {
+        let mut file = self.scene_file()?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let working_dir = std::env::current_dir()?;
+        file.map_paths(|asset| {
+            if asset.is_relative() {
+                relative_path(&working_dir.join(asset), &working_dir.join(dir))
+            } else {
+                asset.to_path_buf()
+            }
+        });
+        std::fs::write(path, file.to_toml()?)?;
+        Ok(())
+    }
+
+    /// The scene as the contents of a TOML file. Paths are written as they
+    /// are, so relative ones are relative to the working directory
+    pub fn to_toml(&self) -> Result<String, SceneFileError> {
+        self.scene_file()?.to_toml()
+    }
+
+    fn scene_file(&self) -> Result<SceneFile, SceneFileError> {
+        let shapes = self
+            .shapes()
+            .iter()
+            .enumerate()
+            .map(|(i, shape)| {
+                shape
+                    .description()
+                    .map(|description| Spanned::new(0..0, description))
+                    .ok_or_else(|| {
+                        SceneFileError::Unsupported(format!(
+                            "shape {i} can't be saved in a scene file"
+                        ))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        let file = SceneFile {
+            camera: Some(Spanned::new(0..0, self.camera().params())),
+            settings: self.settings,
+            materials: BTreeMap::new(),
+            lights: Some(self.lights.clone()),
+            shapes,
+            environment: self
+                .environment
+                .clone()
+                .map(|environment| Spanned::new(0..0, environment)),
+        };
+        Ok(file)
+    }
+}
+
+impl SceneFile {
+    fn to_toml(&self) -> Result<String, SceneFileError> {
+        toml::to_string(self)
+            .map_err(|e| SceneFileError::Unsupported(e.to_string()))
+    }
+
+    /// Replace the path of every file the scene refers to
+    fn map_paths(&mut self, map: impl Fn(&Path) -> PathBuf) {
+        let map_textures = |material: &mut Material| {
+            for texture in material.image_textures_mut() {
+                texture.set_path(map(texture.path()));
+            }
+        };
+        for material in self.materials.values_mut() {
+            map_textures(material.get_mut());
+        }
+        for shape in &mut self.shapes {
+            let material = match shape.get_mut() {
+                ShapeDescription::Mesh { obj, material, .. } => {
+                    *obj = map(obj);
+                    material
+                }
+                ShapeDescription::Sphere { material, .. }
+                | ShapeDescription::Plane { material, .. }
+                | ShapeDescription::Disk { material, .. }
+                | ShapeDescription::Triangle { material, .. } => material,
+            };
+            if let Some(MaterialRef::Inline(material)) = material {
+                map_textures(material);
+            }
+        }
+        if let Some(environment) = &mut self.environment {
+            let environment = environment.get_mut();
+            environment.set_path(map(environment.path()));
+        }
+    }
+}
+
+/// `path` relative to the directory `dir`, both absolute. They're compared
+/// a component at a time, so `..` is used to climb out of `dir`
+fn relative_path(path: &Path, dir: &Path) -> PathBuf {
+    let (path, dir) = (normalised(path), normalised(dir));
+    let shared = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if shared == 0 {
+        // Different drives on Windows, so there's no relative path
+        return path;
+    }
+    let climb = dir.components().count() - shared;
+    (0..climb)
+        .map(|_| Component::ParentDir.as_os_str())
+        .chain(path.components().skip(shared).map(|c| c.as_os_str()))
+        .collect()
+}
+
+/// `path` with `.` components dropped and `..` ones taking away the
+/// component before them
+fn normalised(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalised.pop();
+            }
+            component => normalised.push(component),
+        }
+    }
+    normalised
+}
+
+fn build_shape(
+    description: ShapeDescription,
+    materials: &BTreeMap<String, Spanned<Material>>,
+    base_dir: &Path,
+) -> Result<Box<dyn Shape>, String> {
+    let material = |material: Option<MaterialRef>| match material {
+        None => Ok(Material::default()),
+        Some(MaterialRef::Inline(mut material)) => {
+            load_textures(&mut material, base_dir)?;
+            Ok(*material)
+        }
+        Some(MaterialRef::Named(name)) => materials
+            .get(&name)
+            .map(|material| material.get_ref().clone())
+            .ok_or_else(|| format!("unknown material '{name}'")),
+    };
+    Ok(match description {
+        ShapeDescription::Sphere {
+            center,
+            radius,
+            material: m,
+        } => Box::new(Sphere::new(center, radius, material(m)?)),
+        ShapeDescription::Plane {
+            point,
+            normal,
+            material: m,
+        } => Box::new(Plane::new(point, normal, material(m)?)),
+        ShapeDescription::Disk {
+            center,
+            normal,
+            radius,
+            material: m,
+        } => Box::new(Disk::new(center, normal, radius, material(m)?)),
+        ShapeDescription::Triangle {
+            vertices,
+            normals,
+            material: m,
+        } => Box::new(match normals {
+            Some(normals) => {
+                Triangle::with_normals(vertices, normals, material(m)?)
+            }
+            None => Triangle::new(vertices, material(m)?),
+        }),
+        ShapeDescription::Mesh {
+            obj,
+            position,
+            size,
+            material: m,
+        } => {
+            let mut mesh = Mesh::from_obj(&base_dir.join(&obj)).map_err(
+                |e: ObjError| format!("couldn't load {}: {e}", obj.display()),
+            )?;
+            if let Some(m) = m {
+                let material = material(Some(m))?;
+                mesh.update_material(&|m| *m = material.clone());
+            }
+            if let Some(size) = size {
+                mesh.adjust_size(size - mesh.size());
+            }
+            if let Some(position) = position {
+                mesh.set_position(position);
+            }
+            Box::new(mesh)
+        }
+    })
+}
+
+/// Read the images of a material's textures, relative to `base_dir`, which
+/// is then added to their paths
+fn load_textures(
+    material: &mut Material,
+    base_dir: &Path,
+) -> Result<(), String> {
+    material.image_textures_mut().try_for_each(|texture| {
+        texture.load(base_dir).map_err(|e| {
+            format!("couldn't load {}: {e}", texture.path().display())
+        })?;
+        texture.set_path(base_dir.join(texture.path()));
+        Ok(())
+    })
+}
+
+/// Line number, starting from 1, of a byte offset into `source`
+fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pattern, ProceduralTexture, Texture, GLASS_REFRACTIVE_INDEX};
+
+    fn parse(source: &str) -> Result<Scene, SceneFileError> {
+        Scene::from_toml(source, Path::new(""))
+    }
+
+    fn error_line(source: &str) -> usize {
+        match parse(source) {
+            Err(SceneFileError::Parse { line, .. }) => line,
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("parsed invalid scene"),
+        }
+    }
+
+    #[test]
+    fn loads_shapes_with_named_materials() {
+        let scene = parse(
+            r#"
+            [materials.glass]
+            transparency = 0.9
+            refractive_index = 1.5
+
+            [[shapes]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 100.0
+            material = "glass"
+
+            [[shapes]]
+            type = "plane"
+            point = [0.0, -100.0, 0.0]
+            normal = [0.0, 1.0, 0.0]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scene.shapes().len(), 2);
+        let glass = scene.shape(0).material();
+        assert_eq!(glass.refractive_index(), GLASS_REFRACTIVE_INDEX);
+        assert_eq!(scene.shape(1).material(), &Material::default());
+    }
+
+    #[test]
+    fn empty_file_is_an_empty_scene() {
+        let scene = parse("").unwrap();
+        assert!(scene.shapes().is_empty());
+        assert_eq!(scene.lights(), &[LightSource::default()]);
+        let unlit = parse("lights = []\n").unwrap();
+        assert!(unlit.lights().is_empty());
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut scene = Scene::demo(Camera::default());
+        scene.camera.move_x(20.0);
+        scene.settings.shadows = false;
+        scene.add_light(LightSource::spot(
+            Point::new(0.0, 500.0, 0.0),
+            Vector3D::new(0.0, -1.0, 0.0),
+            30.0,
+            5.0,
+            Vector3D::new(1.0, 0.5, 0.5),
+        ));
+        scene.add_shape(Disk::new(
+            Point::new(0.0, -200.0, 0.0),
+            Vector3D::new(0.0, 1.0, 0.0),
+            300.0,
+            Material::glass(),
+        ));
+
+        let loaded = parse(&scene.to_toml().unwrap()).unwrap();
+        assert_eq!(loaded.camera().params(), scene.camera().params());
+        assert_eq!(loaded.settings, scene.settings);
+        assert_eq!(loaded.lights(), scene.lights());
+
+        while !scene.lights().is_empty() {
+            scene.remove_light(0);
+        }
+        let unlit = parse(&scene.to_toml().unwrap()).unwrap();
+        assert!(unlit.lights().is_empty());
+        let descriptions = |scene: &Scene| {
+            scene
+                .shapes()
+                .iter()
+                .map(|shape| shape.description())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(descriptions(&loaded), descriptions(&scene));
+    }
+
+    #[test]
+    fn example_scene_loads() {
+        let scene = parse(include_str!("../scenes/demo.toml")).unwrap();
+        assert_eq!(scene.shapes().len(), 5);
+    }
+
+    #[test]
+    fn meshes_load_relative_to_scene() {
+        let dir = std::env::temp_dir().join("ray-tracing-scene-file-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("tri.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        let scene = Scene::from_toml(
+            r#"
+            [[shapes]]
+            type = "mesh"
+            obj = "tri.obj"
+            position = [10.0, 20.0, 30.0]
+            size = 5.0
+            material = { reflectivity = 0.5 }
+            "#,
+            &dir,
+        )
+        .unwrap();
+        let mesh = scene.shape(0);
+        assert!(
+            (mesh.position() - Point::new(10.0, 20.0, 30.0)).magnitude() < 1e-9
+        );
+        assert!((mesh.size() - 5.0).abs() < 1e-9);
+        assert_eq!(mesh.material().reflectivity(), 0.5);
+        match mesh.description() {
+            Some(ShapeDescription::Mesh { obj, .. }) => {
+                assert_eq!(obj, dir.join("tri.obj"))
+            }
+            description => panic!("unexpected description {description:?}"),
+        }
+    }
+
+    #[test]
+    fn environment_map_loads_relative_to_scene() {
+        let dir = std::env::temp_dir().join("ray-tracing-environment-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = std::fs::File::create(dir.join("sky.hdr")).unwrap();
+        let sky = vec![image::Rgb([2.5_f32, 1.0, 0.5]); 4 * 2];
+        image::codecs::hdr::HdrEncoder::new(file)
+            .encode(&sky, 4, 2)
+            .unwrap();
+        let source = r#"
+            [environment]
+            path = "sky.hdr"
+            intensity = 2.0
+            "#;
+        let scene = Scene::from_toml(source, &dir).unwrap();
+        let environment = scene.environment.as_ref().unwrap();
+        let radiance = environment.radiance(&Vector3D::new(0.0, 1.0, 0.0));
+        assert!(
+            (radiance - Vector3D::new(5.0, 2.0, 1.0)).magnitude() < 0.1,
+            "{radiance:?}"
+        );
+
+        let saved = Scene::from_toml(&scene.to_toml().unwrap(), &dir).unwrap();
+        assert_eq!(saved.environment, scene.environment);
+
+        let missing = "\n[environment]\npath = \"nope.hdr\"\n";
+        assert_eq!(error_line(missing), 2);
+        let negative = "[environment]\npath = \"sky.hdr\"\nintensity = -1.0\n";
+        assert_eq!(error_line(negative), 3);
+    }
+
+    #[test]
+    fn procedural_textures() {
+        let scene = parse(
+            r#"
+            [[shapes]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 100.0
+            material.albedo_texture = { pattern = "checker", size = 10.0 }
+            "#,
+        )
+        .unwrap();
+        let expected = Texture::from(
+            ProceduralTexture::new(Pattern::Checker).with_size(10.0),
+        );
+        let material = scene.shape(0).material();
+        assert_eq!(material.albedo_texture(), Some(&expected));
+
+        let typo = "[[shapes]]\ntype = \"sphere\"\n\
+                    center = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                    material = { albedo_texture = { pattern = \"marbel\" } }\n";
+        assert_eq!(error_line(typo), 1);
+
+        let valid = "[materials.ok]\n\
+                     albedo_texture = { pattern = \"wood\", octaves = 3 }\n";
+        assert!(parse(valid).is_ok());
+        for value in ["size = 0.0", "size = -5.0", "octaves = 0"] {
+            let source = format!(
+                "[materials.bad]\n\
+                 albedo_texture = {{ pattern = \"wood\", {value} }}\n"
+            );
+            assert_eq!(error_line(&source), 2, "{value}");
+        }
+    }
+
+    #[test]
+    fn textures_load_relative_to_scene() {
+        let dir = std::env::temp_dir().join("ray-tracing-texture-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::from_pixel(2, 2, image::Rgb([255, 0, 0]))
+            .save(dir.join("red.png"))
+            .unwrap();
+        let scene = Scene::from_toml(
+            r#"
+            [materials.red]
+            colour = [1.0, 1.0, 1.0]
+            albedo_texture = { path = "red.png", wrap = "mirror" }
+
+            [[shapes]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 100.0
+            material = "red"
+            "#,
+            &dir,
+        )
+        .unwrap();
+        let surface =
+            scene.shape(0).material().at(&Point::default(), (0.3, 0.6));
+        assert_eq!(surface.colour, Vector3D::new(1.0, 0.0, 0.0));
+
+        let missing = "[[shapes]]\ntype = \"sphere\"\n\
+                       center = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                       material = { albedo_texture = { path = \"nope.png\" } }\n";
+        assert_eq!(error_line(missing), 1);
+    }
+
+    #[test]
+    fn saving_elsewhere_keeps_paths_working() {
+        let root = std::env::temp_dir().join("ray-tracing-save-test");
+        let (assets, saved) = (root.join("assets"), root.join("saved"));
+        std::fs::create_dir_all(&assets).unwrap();
+        std::fs::create_dir_all(&saved).unwrap();
+        std::fs::write(
+            assets.join("tri.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        image::RgbImage::from_pixel(2, 2, image::Rgb([255, 0, 0]))
+            .save(assets.join("red.png"))
+            .unwrap();
+        std::fs::write(
+            assets.join("scene.toml"),
+            r#"
+            [[shapes]]
+            type = "mesh"
+            obj = "tri.obj"
+
+            [[shapes]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 100.0
+            material.colour = [1.0, 1.0, 1.0]
+            material.albedo_texture = { path = "red.png" }
+
+            [environment]
+            path = "red.png"
+            "#,
+        )
+        .unwrap();
+
+        let scene = Scene::load(&assets.join("scene.toml")).unwrap();
+        scene.save(&saved.join("scene.toml")).unwrap();
+        let loaded = Scene::load(&saved.join("scene.toml")).unwrap();
+        assert_eq!(loaded.shapes().len(), 2);
+        let surface =
+            loaded.shape(1).material().at(&Point::default(), (0.3, 0.6));
+        assert_eq!(surface.colour, Vector3D::new(1.0, 0.0, 0.0));
+        let environment = loaded.environment.as_ref().unwrap();
+        let radiance = environment.radiance(&Vector3D::new(0.0, 1.0, 0.0));
+        assert_eq!(radiance, Vector3D::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn relative_paths_climb_out_of_directory() {
+        let relative = |path: &str, dir: &str| {
+            relative_path(Path::new(path), Path::new(dir))
+        };
+        assert_eq!(relative("/a/b/c.png", "/a/b"), PathBuf::from("c.png"));
+        assert_eq!(
+            relative("/a/b/c.png", "/a/d/e"),
+            PathBuf::from("../../b/c.png")
+        );
+        assert_eq!(
+            relative("/a/./b/../c.png", "/a/d/../e"),
+            PathBuf::from("../c.png")
+        );
+    }
+
+    #[test]
+    fn syntax_errors_report_line() {
+        assert_eq!(error_line("[camera]\nfov = 45.0\nwidth = \n"), 3);
+        assert_eq!(error_line("[camera]\nwidth = 640\nheight = 0\n"), 3);
+    }
+
+    #[test]
+    fn camera_on_its_target_reports_line() {
+        let source = "[settings]\nshadows = true\n\n[camera]\n\
+                      position = [1.0, 2.0, 3.0]\ntarget = [1.0, 2.0, 3.0]\n";
+        assert_eq!(error_line(source), 4);
+    }
+
+    #[test]
+    fn unknown_fields_report_line() {
+        let source = "[camera]\nfov = 45.0\n\n[[shapes]]\ntype = \"sphere\"\n\
+                      center = [0.0, 0.0, 0.0]\nradius = 1.0\nradius2 = 2.0\n";
+        assert_eq!(error_line(source), 4);
+    }
+
+    #[test]
+    fn out_of_range_material_values_report_line() {
+        let shape = "[[shapes]]\ntype = \"sphere\"\n\
+                     center = [0.0, 0.0, 0.0]\nradius = 1.0\n";
+        for value in [
+            "transparency = 5.0",
+            "reflectivity = -2.0",
+            "refractive_index = 0.0",
+            "emission = [1.0, -1.0, 0.0]",
+            "microfacet = { metallic = 1.5 }",
+        ] {
+            let source = format!("[materials.bad]\n{value}\n\n{shape}");
+            match parse(&source) {
+                Err(SceneFileError::Parse { line, message }) => {
+                    assert_eq!(line, 2, "{value}: {message}")
+                }
+                _ => panic!("{value} was accepted"),
+            }
+        }
+        assert!(parse("[materials.ok]\ntransparency = 1.0\n").is_ok());
+    }
+
+    #[test]
+    fn unknown_materials_report_line() {
+        let source = "[[shapes]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\n\
+                      radius = 1.0\n\n[[shapes]]\ntype = \"sphere\"\n\
+                      center = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"glas\"\n";
+        assert_eq!(error_line(source), 6);
+    }
+}
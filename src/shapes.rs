@@ -1,26 +1,142 @@
 use crate::{
-    Camera, ColourChannel, Intersection, Material, PixelColour, Point, Ray,
-    Vector3D,
+    Aabb, ColourChannel, Intersection, Material, MaterialRef, PixelColour,
+    Point, Ray, ShapeDescription, Vector3D,
 };
 use std::cmp::Ordering;
+use std::f64::consts::{PI, TAU};
 
-pub trait Shape: Sync {
+/// Rays travelling closer than this to parallel with a flat surface are
+/// treated as missing it, as are hits closer than this to the ray's origin
+const EPSILON: f64 = 1e-9;
+
+/// Distance covered by one repeat of a texture on an infinite plane
+const PLANE_TEXTURE_SIZE: f64 = 100.0;
+
+/// A direction towards a shape picked by `Shape::sample_direction`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DirectionSample {
+    /// Unit vector from the point being lit towards the shape
+    pub direction: Vector3D,
+    /// Distance to the shape's surface along `direction`
+    pub distance: f64,
+    /// Probability density of picking `direction`, per unit solid angle
+    pub pdf: f64,
+}
+
+/// How a point on a shape moves as its texture coordinates change, which
+/// lines textures that tilt the normal up with the surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TangentFrame {
+    /// Rate of change of the point with the u texture coordinate
+    pub dpdu: Vector3D,
+    /// Rate of change of the point with the v texture coordinate
+    pub dpdv: Vector3D,
+}
+
+pub trait Shape: Send + Sync + ShapeClone {
     /// Calculate where the closes intersection between a ray and the surface of a
     /// shape is, relative to the origin of the ray, if it exists
-    fn intersection<'a>(
-        &'a self,
-        ray: &'a Ray,
-        camera: &Camera,
-    ) -> Option<Intersection>;
+    fn intersection<'a>(&'a self, ray: &'a Ray) -> Option<Intersection<'a>>;
 
     /// Calculate the surface normal for a point on the shape, normalised to
     /// a unit vector
     fn surface_normal(&self, point: &Point) -> Vector3D;
 
-    fn material(&self) -> Material;
+    /// Texture coordinates of a point on the shape, used to look up image
+    /// textures. They go from 0.0 to 1.0 across the shape where it has an
+    /// edge. Shapes without a mapping of their own map everything to the
+    /// corner of the texture
+    fn uv(&self, _point: &Point) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    /// Tangents along the texture coordinates at a point on the shape.
+    /// Shapes without a mapping of their own use any two unit vectors at
+    /// right angles in the surface
+    fn tangent_frame(&self, point: &Point) -> TangentFrame {
+        let (dpdu, dpdv) = self.surface_normal(point).orthonormal_basis();
+        TangentFrame { dpdu, dpdv }
+    }
+
+    /// Smallest axis-aligned box containing the whole shape, used to skip
+    /// intersection tests for rays which can't possibly hit it. Shapes which
+    /// go on forever should return `Aabb::infinite()`
+    fn bounding_box(&self) -> Aabb;
+
+    fn material(&self) -> &Material;
+
+    fn material_mut(&mut self) -> &mut Material;
+
+    /// The point the shape is positioned around, e.g. the center of a sphere
+    fn position(&self) -> Point;
+
+    fn set_position(&mut self, new_position: Point);
+
+    /// The characteristic size of the shape, e.g. the radius of a sphere
+    fn size(&self) -> f64;
+
+    fn adjust_size(&mut self, delta: f64);
+
+    /// How the shape is written to a scene file, or `None` if it can't be
+    fn description(&self) -> Option<ShapeDescription> {
+        None
+    }
+
+    /// Pick a direction from `from` towards the shape, so emissive shapes
+    /// can be sampled like lights. `u` is a pair of uniform random numbers
+    /// in `[0, 1)`. `None` if the shape doesn't support it or can't be seen
+    fn sample_direction(
+        &self,
+        _from: &Point,
+        _u: (f64, f64),
+    ) -> Option<DirectionSample> {
+        None
+    }
+
+    /// Probability density of `sample_direction` picking the unit vector
+    /// `direction` from `from`
+    fn direction_pdf(&self, _from: &Point, _direction: &Vector3D) -> f64 {
+        0.0
+    }
+
+    /// Apply a change to every material making up the shape. Shapes built
+    /// out of other shapes (e.g. meshes) override this to reach all of them
+    fn update_material(&mut self, update: &dyn Fn(&mut Material)) {
+        update(self.material_mut());
+    }
+
+    fn set_colour_channel(&mut self, channel: &ColourChannel, value: u8) {
+        self.update_material(&|m| m.set_colour_channel(channel, value));
+    }
+
+    fn set_reflectivity(&mut self, value: f64) {
+        self.update_material(&|m| m.set_reflectivity(value));
+    }
+
+    fn set_transparency(&mut self, value: f64) {
+        self.update_material(&|m| m.set_transparency(value));
+    }
+}
+
+/// Lets boxed shapes be cloned, which `Clone` can't do for trait objects.
+/// Every `Shape` which is `Clone` gets this for free
+pub trait ShapeClone {
+    fn clone_box(&self) -> Box<dyn Shape>;
+}
+
+impl<T: Shape + Clone + 'static> ShapeClone for T {
+    fn clone_box(&self) -> Box<dyn Shape> {
+        Box::new(self.clone())
+    }
 }
 
-#[derive(Copy, Clone)]
+impl Clone for Box<dyn Shape> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point,
     pub radius: f64,
@@ -46,9 +162,10 @@ impl Sphere {
     }
 
     pub fn default_with_pos(c: Point) -> Self {
-        let mut sphere = Self::default();
-        sphere.center = c;
-        sphere
+        Self {
+            center: c,
+            ..Self::default()
+        }
     }
 
     pub fn adjust_radius(&mut self, delta: f64) {
@@ -72,12 +189,26 @@ impl Sphere {
     }
 
     pub fn set_colour(&mut self, new_colour: &PixelColour) {
-        self.material.set_colour(&new_colour);
+        self.material.set_colour(new_colour);
     }
 
     pub fn set_colour_channel(&mut self, channel: &ColourChannel, value: u8) {
         self.material.set_colour_channel(channel, value);
     }
+
+    /// The cone of directions the sphere fills as seen from `from`: its
+    /// axis towards the centre, the distance to the centre and the cosine of
+    /// the cone's half angle. `None` from inside the sphere
+    fn visible_cone(&self, from: &Point) -> Option<(Vector3D, f64, f64)> {
+        let mut axis = self.center - *from;
+        let distance = axis.magnitude();
+        if distance <= self.radius {
+            return None;
+        }
+        axis.normalise();
+        let sin2_max = (self.radius / distance).powi(2);
+        Some((axis, distance, (1.0 - sin2_max).sqrt()))
+    }
 }
 
 impl Default for Sphere {
@@ -91,24 +222,13 @@ impl Default for Sphere {
 }
 
 impl Shape for Sphere {
-    fn intersection<'a>(
-        &'a self,
-        ray: &'a Ray,
-        camera: &Camera,
-    ) -> Option<Intersection> {
+    fn intersection<'a>(&'a self, ray: &'a Ray) -> Option<Intersection<'a>> {
         let v = ray.origin - self.center;
         let a = ray.direction.dot(&ray.direction);
         let b = 2.0 * (v.dot(&ray.direction));
         let c = v.dot(&v) - (self.radius * self.radius); //* self.scale(ray));
         if let Some((t, is_inside)) = solve_t(a, b, c) {
-            Some(Intersection::new(
-                t,
-                ray.point(t),
-                self,
-                ray,
-                camera.light_source(),
-                is_inside,
-            ))
+            Some(Intersection::new(t, ray.point(t), self, ray, is_inside))
         } else {
             None
         }
@@ -121,11 +241,513 @@ impl Shape for Sphere {
         surface_normal
     }
 
-    fn material(&self) -> Material {
-        self.material
+    /// Longitude and latitude, with the texture's top edge at the top of the
+    /// sphere and its left and right edges meeting behind it
+    fn uv(&self, point: &Point) -> (f64, f64) {
+        let d = self.surface_normal(point);
+        (0.5 + d.z.atan2(d.x) / TAU, d.y.clamp(-1.0, 1.0).acos() / PI)
+    }
+
+    /// Along the lines of latitude and longitude, except at the poles where
+    /// they all meet
+    fn tangent_frame(&self, point: &Point) -> TangentFrame {
+        let d = self.surface_normal(point);
+        let sin = (d.x * d.x + d.z * d.z).sqrt();
+        if sin < EPSILON {
+            let (dpdu, dpdv) = d.orthonormal_basis();
+            return TangentFrame { dpdu, dpdv };
+        }
+        let r = self.radius;
+        TangentFrame {
+            dpdu: Vector3D::new(-d.z, 0.0, d.x) * (TAU * r),
+            dpdv: Vector3D::new(d.y * d.x / sin, -sin, d.y * d.z / sin)
+                * (PI * r),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vector3D::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - r, self.center + r)
+    }
+
+    /// Picks uniformly from the cone of directions the sphere fills
+    fn sample_direction(
+        &self,
+        from: &Point,
+        u: (f64, f64),
+    ) -> Option<DirectionSample> {
+        let (axis, distance, cos_max) = self.visible_cone(from)?;
+        let cos = 1.0 - u.0 * (1.0 - cos_max);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = TAU * u.1;
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let mut direction = tangent * (sin * phi.cos())
+            + bitangent * (sin * phi.sin())
+            + axis * cos;
+        direction.normalise();
+        // Distance to the near side of the sphere along the direction
+        let half_chord = (self.radius * self.radius
+            - distance * distance * sin * sin)
+            .max(0.0)
+            .sqrt();
+        Some(DirectionSample {
+            direction,
+            distance: distance * cos - half_chord,
+            pdf: self.direction_pdf(from, &direction),
+        })
+    }
+
+    fn direction_pdf(&self, from: &Point, direction: &Vector3D) -> f64 {
+        match self.visible_cone(from) {
+            Some((axis, distance, cos_max))
+                if axis.dot(direction) >= cos_max =>
+            {
+                // 1 - cos_max, written so it doesn't lose precision for
+                // small or faraway spheres
+                let sin2_max = (self.radius / distance).powi(2);
+                1.0 / (TAU * sin2_max / (1.0 + cos_max))
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn position(&self) -> Point {
+        self.center
+    }
+
+    fn set_position(&mut self, new_position: Point) {
+        self.center = new_position;
+    }
+
+    fn size(&self) -> f64 {
+        self.radius
+    }
+
+    fn adjust_size(&mut self, delta: f64) {
+        self.adjust_radius(delta);
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Sphere {
+            center: self.center,
+            radius: self.radius,
+            material: Some(MaterialRef::Inline(Box::new(
+                self.material.clone(),
+            ))),
+        })
+    }
+}
+
+/// An infinite flat surface passing through `point`, facing in the direction
+/// of `normal`
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Point,
+    pub normal: Vector3D,
+    pub material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Point, mut normal: Vector3D, material: Material) -> Self {
+        normal.normalise();
+        Plane {
+            point,
+            normal,
+            material,
+        }
+    }
+
+    pub fn new_with_colour(
+        point: Point,
+        normal: Vector3D,
+        colour: PixelColour,
+    ) -> Self {
+        Self::new(point, normal, Material::default_with_colour(colour))
     }
 }
 
+impl Shape for Plane {
+    fn intersection<'a>(&'a self, ray: &'a Ray) -> Option<Intersection<'a>> {
+        solve_plane_t(ray, &self.point, &self.normal)
+            .map(|t| Intersection::new(t, ray.point(t), self, ray, false))
+    }
+
+    fn surface_normal(&self, _point: &Point) -> Vector3D {
+        self.normal
+    }
+
+    /// The texture repeats every `PLANE_TEXTURE_SIZE` across the plane
+    fn uv(&self, point: &Point) -> (f64, f64) {
+        let (u, v) = planar_coordinates(&self.point, &self.normal, point);
+        (u / PLANE_TEXTURE_SIZE, v / PLANE_TEXTURE_SIZE)
+    }
+
+    fn tangent_frame(&self, _point: &Point) -> TangentFrame {
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        TangentFrame {
+            dpdu: tangent * PLANE_TEXTURE_SIZE,
+            dpdv: bitangent * PLANE_TEXTURE_SIZE,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::infinite()
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn position(&self) -> Point {
+        self.point
+    }
+
+    fn set_position(&mut self, new_position: Point) {
+        self.point = new_position;
+    }
+
+    fn size(&self) -> f64 {
+        f64::INFINITY
+    }
+
+    fn adjust_size(&mut self, _delta: f64) {}
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Plane {
+            point: self.point,
+            normal: self.normal,
+            material: Some(MaterialRef::Inline(Box::new(
+                self.material.clone(),
+            ))),
+        })
+    }
+}
+
+/// A flat circle centered on `center`, facing in the direction of `normal`
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Point,
+    pub normal: Vector3D,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl Disk {
+    pub fn new(
+        center: Point,
+        mut normal: Vector3D,
+        radius: f64,
+        material: Material,
+    ) -> Self {
+        normal.normalise();
+        Disk {
+            center,
+            normal,
+            radius,
+            material,
+        }
+    }
+
+    pub fn new_with_colour(
+        center: Point,
+        normal: Vector3D,
+        radius: f64,
+        colour: PixelColour,
+    ) -> Self {
+        Self::new(
+            center,
+            normal,
+            radius,
+            Material::default_with_colour(colour),
+        )
+    }
+
+    pub fn adjust_radius(&mut self, delta: f64) {
+        self.radius += delta;
+    }
+}
+
+impl Shape for Disk {
+    fn intersection<'a>(&'a self, ray: &'a Ray) -> Option<Intersection<'a>> {
+        solve_disk_t(ray, &self.center, &self.normal, self.radius)
+            .map(|t| Intersection::new(t, ray.point(t), self, ray, false))
+    }
+
+    fn surface_normal(&self, _point: &Point) -> Vector3D {
+        self.normal
+    }
+
+    /// The texture is stretched over the square the disk fits in
+    fn uv(&self, point: &Point) -> (f64, f64) {
+        let (u, v) = planar_coordinates(&self.center, &self.normal, point);
+        let diameter = 2.0 * self.radius;
+        (0.5 + u / diameter, 0.5 + v / diameter)
+    }
+
+    fn tangent_frame(&self, _point: &Point) -> TangentFrame {
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let diameter = 2.0 * self.radius;
+        TangentFrame {
+            dpdu: tangent * diameter,
+            dpdv: bitangent * diameter,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // How far the rim of the disk reaches along each axis
+        let n = self.normal;
+        let extent = Vector3D::new(
+            (1.0 - n.x * n.x).max(0.0).sqrt(),
+            (1.0 - n.y * n.y).max(0.0).sqrt(),
+            (1.0 - n.z * n.z).max(0.0).sqrt(),
+        ) * self.radius;
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn position(&self) -> Point {
+        self.center
+    }
+
+    fn set_position(&mut self, new_position: Point) {
+        self.center = new_position;
+    }
+
+    fn size(&self) -> f64 {
+        self.radius
+    }
+
+    fn adjust_size(&mut self, delta: f64) {
+        self.adjust_radius(delta);
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Disk {
+            center: self.center,
+            normal: self.normal,
+            radius: self.radius,
+            material: Some(MaterialRef::Inline(Box::new(
+                self.material.clone(),
+            ))),
+        })
+    }
+}
+
+/// A single flat triangle, optionally with a normal per vertex which are
+/// interpolated across its surface for smooth shading
+#[derive(Clone)]
+pub struct Triangle {
+    pub vertices: [Point; 3],
+    pub normals: Option<[Vector3D; 3]>,
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point; 3], material: Material) -> Self {
+        Triangle {
+            vertices,
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(
+        vertices: [Point; 3],
+        mut normals: [Vector3D; 3],
+        material: Material,
+    ) -> Self {
+        normals.iter_mut().for_each(|n| n.normalise());
+        Triangle {
+            vertices,
+            normals: Some(normals),
+            material,
+        }
+    }
+
+    /// Normal of the plane the triangle lies in, following the right hand
+    /// rule for the order of the vertices
+    pub fn face_normal(&self) -> Vector3D {
+        let [v0, v1, v2] = self.vertices;
+        let mut normal = (v1 - v0) * (v2 - v0);
+        normal.normalise();
+        normal
+    }
+
+    pub fn centroid(&self) -> Point {
+        let [v0, v1, v2] = self.vertices;
+        (v0 + v1 + v2) / 3.0
+    }
+
+    pub fn translate(&mut self, offset: Vector3D) {
+        self.vertices.iter_mut().for_each(|v| *v = *v + offset);
+    }
+
+    /// Scale the triangle's distance from `origin` by `factor`
+    pub fn scale_about(&mut self, origin: Point, factor: f64) {
+        self.vertices
+            .iter_mut()
+            .for_each(|v| *v = origin + (*v - origin) * factor);
+    }
+
+    /// Barycentric weights (w0, w1, w2) of a point lying on the triangle
+    fn barycentric(&self, point: &Point) -> (f64, f64, f64) {
+        let [v0, v1, v2] = self.vertices;
+        let e1 = v1 - v0;
+        let e2 = v2 - v0;
+        let p = *point - v0;
+        let d11 = e1.dot(&e1);
+        let d12 = e1.dot(&e2);
+        let d22 = e2.dot(&e2);
+        let dp1 = p.dot(&e1);
+        let dp2 = p.dot(&e2);
+        let denominator = d11 * d22 - d12 * d12;
+        if denominator.abs() < EPSILON {
+            return (1.0, 0.0, 0.0);
+        }
+        let w1 = (d22 * dp1 - d12 * dp2) / denominator;
+        let w2 = (d11 * dp2 - d12 * dp1) / denominator;
+        (1.0 - w1 - w2, w1, w2)
+    }
+}
+
+impl Shape for Triangle {
+    fn intersection<'a>(&'a self, ray: &'a Ray) -> Option<Intersection<'a>> {
+        solve_triangle_t(ray, &self.vertices)
+            .map(|t| Intersection::new(t, ray.point(t), self, ray, false))
+    }
+
+    /// The texture's top left corner is at the first vertex, with the
+    /// second and third vertices along its top and left edges
+    fn uv(&self, point: &Point) -> (f64, f64) {
+        let (_, w1, w2) = self.barycentric(point);
+        (w1, w2)
+    }
+
+    fn tangent_frame(&self, _point: &Point) -> TangentFrame {
+        let [v0, v1, v2] = self.vertices;
+        TangentFrame {
+            dpdu: v1 - v0,
+            dpdv: v2 - v0,
+        }
+    }
+
+    fn surface_normal(&self, point: &Point) -> Vector3D {
+        if let Some([n0, n1, n2]) = self.normals {
+            let (w0, w1, w2) = self.barycentric(point);
+            let mut normal = n0 * w0 + n1 * w1 + n2 * w2;
+            normal.normalise();
+            normal
+        } else {
+            self.face_normal()
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.vertices)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn position(&self) -> Point {
+        self.centroid()
+    }
+
+    fn set_position(&mut self, new_position: Point) {
+        self.translate(new_position - self.centroid());
+    }
+
+    fn size(&self) -> f64 {
+        let centroid = self.centroid();
+        self.vertices
+            .iter()
+            .map(|v| (*v - centroid).magnitude())
+            .fold(0.0, f64::max)
+    }
+
+    fn adjust_size(&mut self, delta: f64) {
+        let size = self.size();
+        if size > 0.0 {
+            self.scale_about(self.centroid(), (size + delta).max(0.0) / size);
+        }
+    }
+
+    fn description(&self) -> Option<ShapeDescription> {
+        Some(ShapeDescription::Triangle {
+            vertices: self.vertices,
+            normals: self.normals,
+            material: Some(MaterialRef::Inline(Box::new(
+                self.material.clone(),
+            ))),
+        })
+    }
+}
+
+/// Coordinates of `point` on the plane through `origin` with the given unit
+/// `normal`, along two axes at right angles in the plane
+fn planar_coordinates(
+    origin: &Point,
+    normal: &Vector3D,
+    point: &Point,
+) -> (f64, f64) {
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    let offset = *point - *origin;
+    (offset.dot(&tangent), offset.dot(&bitangent))
+}
+
+/// Distance along a ray to the plane through `point` with the given `normal`,
+/// if the ray hits it in front of its origin
+fn solve_plane_t(ray: &Ray, point: &Point, normal: &Vector3D) -> Option<f64> {
+    let denominator = normal.dot(&ray.direction);
+    if denominator.abs() < EPSILON {
+        // Ray is parallel to the plane, so either never touches it or slides
+        // along inside it
+        return None;
+    }
+    let t = (*point - ray.origin).dot(normal) / denominator;
+    if t > EPSILON {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+fn solve_disk_t(
+    ray: &Ray,
+    center: &Point,
+    normal: &Vector3D,
+    radius: f64,
+) -> Option<f64> {
+    solve_plane_t(ray, center, normal).filter(|&t| {
+        let offset = ray.point(t) - *center;
+        offset.dot(&offset) <= radius * radius
+    })
+}
+
 fn solve_t(a: f64, b: f64, c: f64) -> Option<(f64, bool)> {
     let discriminant = (b * b) - (4.0 * a * c);
     match discriminant.total_cmp(&0.0) {
@@ -149,6 +771,40 @@ fn solve_t(a: f64, b: f64, c: f64) -> Option<(f64, bool)> {
     }
 }
 
+/// Möller–Trumbore ray/triangle intersection, returning the distance along
+/// the ray to the hit point
+fn solve_triangle_t(ray: &Ray, vertices: &[Point; 3]) -> Option<f64> {
+    let [v0, v1, v2] = *vertices;
+    let edge_1 = v1 - v0;
+    let edge_2 = v2 - v0;
+    let p = ray.direction * edge_2;
+    let determinant = edge_1.dot(&p);
+    if determinant.abs() < EPSILON {
+        // Ray is parallel to the triangle
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = ray.origin - v0;
+    let u = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s * edge_1;
+    let v = ray.direction.dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge_2.dot(&q) * inverse_determinant;
+    if t > EPSILON {
+        Some(t)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,6 +824,236 @@ mod tests {
         assert_eq!(solve_t(1.0, 2.0, 1.0), Some((-1.0, false)));
     }
 
+    fn test_ray(origin: Point, direction: Vector3D) -> Ray {
+        Ray { origin, direction }
+    }
+
+    #[test]
+    fn ray_hits_plane() {
+        let ray = test_ray(
+            Point::new(0.0, 100.0, 0.0),
+            Vector3D::new(0.0, -1.0, 0.0),
+        );
+        let point = Point::new(0.0, -50.0, 0.0);
+        let normal = Vector3D::new(0.0, 1.0, 0.0);
+        assert_eq!(solve_plane_t(&ray, &point, &normal), Some(150.0));
+    }
+
+    #[test]
+    fn ray_hits_back_of_plane() {
+        let ray = test_ray(
+            Point::new(0.0, -100.0, 0.0),
+            Vector3D::new(0.0, 1.0, 0.0),
+        );
+        let point = Point::new(0.0, -50.0, 0.0);
+        let normal = Vector3D::new(0.0, 1.0, 0.0);
+        assert_eq!(solve_plane_t(&ray, &point, &normal), Some(50.0));
+    }
+
+    #[test]
+    fn ray_misses_plane_behind_it() {
+        let ray =
+            test_ray(Point::new(0.0, 100.0, 0.0), Vector3D::new(0.0, 1.0, 0.0));
+        let point = Point::new(0.0, -50.0, 0.0);
+        let normal = Vector3D::new(0.0, 1.0, 0.0);
+        assert!(solve_plane_t(&ray, &point, &normal).is_none());
+    }
+
+    #[test]
+    fn grazing_ray_misses_plane() {
+        let point = Point::new(0.0, -50.0, 0.0);
+        let normal = Vector3D::new(0.0, 1.0, 0.0);
+        let parallel =
+            test_ray(Point::new(0.0, 100.0, 0.0), Vector3D::new(1.0, 0.0, 0.0));
+        let in_plane =
+            test_ray(Point::new(0.0, -50.0, 0.0), Vector3D::new(1.0, 0.0, 0.0));
+        assert!(solve_plane_t(&parallel, &point, &normal).is_none());
+        assert!(solve_plane_t(&in_plane, &point, &normal).is_none());
+    }
+
+    #[test]
+    fn ray_hits_disk() {
+        let ray = test_ray(
+            Point::new(30.0, 0.0, -100.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+        );
+        let center = Point::new(0.0, 0.0, 0.0);
+        let normal = Vector3D::new(0.0, 0.0, -1.0);
+        assert_eq!(solve_disk_t(&ray, &center, &normal, 50.0), Some(100.0));
+    }
+
+    #[test]
+    fn ray_misses_disk_outside_radius() {
+        let ray = test_ray(
+            Point::new(60.0, 0.0, -100.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+        );
+        let center = Point::new(0.0, 0.0, 0.0);
+        let normal = Vector3D::new(0.0, 0.0, -1.0);
+        assert!(solve_disk_t(&ray, &center, &normal, 50.0).is_none());
+    }
+
+    #[test]
+    fn ray_hits_disk_edge() {
+        let ray = test_ray(
+            Point::new(50.0, 0.0, -100.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+        );
+        let center = Point::new(0.0, 0.0, 0.0);
+        let normal = Vector3D::new(0.0, 0.0, -1.0);
+        assert_eq!(solve_disk_t(&ray, &center, &normal, 50.0), Some(100.0));
+    }
+
+    #[test]
+    fn grazing_ray_misses_disk() {
+        let ray = test_ray(
+            Point::new(-100.0, 0.0, 0.0),
+            Vector3D::new(1.0, 0.0, 0.0),
+        );
+        let center = Point::new(0.0, 0.0, 0.0);
+        let normal = Vector3D::new(0.0, 0.0, -1.0);
+        assert!(solve_disk_t(&ray, &center, &normal, 50.0).is_none());
+    }
+
+    #[test]
+    fn texture_coordinates() {
+        let sphere = Sphere::default_with_pos(Point::new(0.0, 0.0, 0.0));
+        assert_eq!(sphere.uv(&Point::new(0.0, 100.0, 0.0)).1, 0.0);
+        assert_eq!(sphere.uv(&Point::new(-100.0, 0.0, 0.0)), (1.0, 0.5));
+
+        let disk = Disk::new(
+            Point::new(0.0, 0.0, 0.0),
+            Vector3D::new(0.0, 1.0, 0.0),
+            50.0,
+            Material::default(),
+        );
+        assert_eq!(disk.uv(&disk.center), (0.5, 0.5));
+
+        let triangle = Triangle::new(test_triangle(), Material::default());
+        let [v0, v1, v2] = test_triangle();
+        assert_eq!(triangle.uv(&v0), (0.0, 0.0));
+        assert_eq!(triangle.uv(&v1), (1.0, 0.0));
+        assert_eq!(triangle.uv(&v2), (0.0, 1.0));
+    }
+
+    #[test]
+    fn tangent_frames_follow_texture_coordinates() {
+        let mut normal = Vector3D::new(0.3, 1.0, -0.2);
+        normal.normalise();
+        let shapes: Vec<(Box<dyn Shape>, Point)> = vec![
+            (
+                Box::new(Sphere::default_with_pos(Point::new(10.0, 0.0, 0.0))),
+                Point::new(10.0 + 60.0, 48.0, -64.0),
+            ),
+            (
+                Box::new(Plane::new(
+                    Point::new(0.0, 0.0, 0.0),
+                    normal,
+                    Material::default(),
+                )),
+                Point::new(0.0, 0.0, 0.0),
+            ),
+            (
+                Box::new(Disk::new(
+                    Point::new(0.0, 0.0, 0.0),
+                    normal,
+                    50.0,
+                    Material::default(),
+                )),
+                Point::new(0.0, 0.0, 0.0),
+            ),
+            (
+                Box::new(Triangle::new(test_triangle(), Material::default())),
+                Point::new(0.0, -10.0, 0.0),
+            ),
+        ];
+        let step = 1e-6;
+        for (shape, point) in shapes {
+            let frame = shape.tangent_frame(&point);
+            let (u, v) = shape.uv(&point);
+            let (u1, v1) = shape.uv(&(point + frame.dpdu * step));
+            let (u2, v2) = shape.uv(&(point + frame.dpdv * step));
+            assert!((u1 - u - step).abs() < step * 1e-3, "{u1} {u}");
+            assert!((v1 - v).abs() < step * 1e-3);
+            assert!((u2 - u).abs() < step * 1e-3);
+            assert!((v2 - v - step).abs() < step * 1e-3, "{v2} {v}");
+        }
+    }
+
+    #[test]
+    fn sphere_direction_samples_hit_it() {
+        let sphere = Sphere::default_with_pos(Point::new(0.0, 0.0, 300.0));
+        let from = Point::new(0.0, 50.0, -200.0);
+        for u in [(0.0, 0.0), (0.5, 0.25), (0.999, 0.9)] {
+            let sample = sphere.sample_direction(&from, u).unwrap();
+            let ray = test_ray(from, sample.direction);
+            let hit = sphere.intersection(&ray).unwrap();
+            assert!((hit.t() - sample.distance).abs() < 1e-6);
+            assert!(sample.pdf > 0.0);
+            assert_eq!(sample.pdf, sphere.direction_pdf(&from, &ray.direction));
+        }
+        let away = Vector3D::new(0.0, 0.0, -1.0);
+        assert_eq!(sphere.direction_pdf(&from, &away), 0.0);
+        assert!(sphere
+            .sample_direction(&sphere.center, (0.5, 0.5))
+            .is_none());
+    }
+
+    fn test_triangle() -> [Point; 3] {
+        [
+            Point::new(-50.0, -50.0, 0.0),
+            Point::new(50.0, -50.0, 0.0),
+            Point::new(0.0, 50.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn ray_hits_triangle() {
+        let ray = test_ray(
+            Point::new(0.0, 0.0, -100.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+        );
+        assert_eq!(solve_triangle_t(&ray, &test_triangle()), Some(100.0));
+    }
+
+    #[test]
+    fn ray_misses_triangle() {
+        let ray = test_ray(
+            Point::new(40.0, 40.0, -100.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+        );
+        assert!(solve_triangle_t(&ray, &test_triangle()).is_none());
+    }
+
+    #[test]
+    fn ray_misses_triangle_behind_it() {
+        let ray =
+            test_ray(Point::new(0.0, 0.0, 100.0), Vector3D::new(0.0, 0.0, 1.0));
+        assert!(solve_triangle_t(&ray, &test_triangle()).is_none());
+    }
+
+    #[test]
+    fn grazing_ray_misses_triangle() {
+        let ray = test_ray(
+            Point::new(-100.0, 0.0, 0.0),
+            Vector3D::new(1.0, 0.0, 0.0),
+        );
+        assert!(solve_triangle_t(&ray, &test_triangle()).is_none());
+    }
+
+    #[test]
+    fn smooth_triangle_interpolates_normals() {
+        let up = Vector3D::new(0.0, 1.0, 0.0);
+        let right = Vector3D::new(1.0, 0.0, 0.0);
+        let triangle = Triangle::with_normals(
+            test_triangle(),
+            [up, right, up],
+            Material::default(),
+        );
+        assert_eq!(triangle.surface_normal(&test_triangle()[1]), right);
+        assert_eq!(triangle.surface_normal(&test_triangle()[2]), up);
+    }
+
     // #[test]
     // fn ray_hits_sphere() {
     //     let c = Point::new(0.0, 0.0, 0.0);
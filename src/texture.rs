@@ -0,0 +1,324 @@
+use crate::{LightColour, Point, ProceduralTexture};
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{DynamicImage, ImageError, ImageResult};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// How texture coordinates outside `[0, 1)` are mapped back onto the image
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    /// Tile the image
+    #[default]
+    Repeat,
+    /// Stretch the pixels along the edges of the image
+    Clamp,
+    /// Tile the image, flipping every other copy so the edges line up
+    Mirror,
+}
+
+impl WrapMode {
+    /// Map a pixel index onto an image `size` pixels across
+    fn wrap(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            Self::Repeat => i.rem_euclid(size),
+            Self::Clamp => i.clamp(0, size - 1),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+/// A colour which varies over the surface of a shape, multiplied with one of
+/// a material's properties. In scene files, image textures have a `path` and
+/// procedural ones a `pattern`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "an image texture with a `path`, or a procedural texture \
+                 with a `pattern`, a `size` above 0 and at least 1 octave"
+)]
+pub enum Texture {
+    Image(ImageTexture),
+    Procedural(ProceduralTexture),
+}
+
+impl Texture {
+    /// Colour of the texture at `point` in the scene, which is at texture
+    /// coordinates `uv` on the shape
+    pub fn sample(&self, point: &Point, uv: (f64, f64)) -> LightColour {
+        match self {
+            Self::Image(image) => image.sample(uv),
+            Self::Procedural(procedural) => procedural.sample(point),
+        }
+    }
+}
+
+impl From<ImageTexture> for Texture {
+    fn from(texture: ImageTexture) -> Self {
+        Self::Image(texture)
+    }
+}
+
+impl From<ProceduralTexture> for Texture {
+    fn from(texture: ProceduralTexture) -> Self {
+        Self::Procedural(texture)
+    }
+}
+
+/// An image mapped onto shapes with their texture coordinates, sampled with
+/// bilinear filtering. Clones share the same pixels
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageTexture {
+    /// The image file. In scene files, it's relative to the scene file
+    path: PathBuf,
+    #[serde(default)]
+    wrap: WrapMode,
+    /// How many times the image repeats across the texture coordinates, on
+    /// each axis
+    #[serde(default = "default_scale")]
+    scale: [f64; 2],
+    /// A single white pixel until the image is loaded, so unloaded textures
+    /// don't change anything
+    #[serde(skip)]
+    image: Arc<TextureImage>,
+}
+
+fn default_scale() -> [f64; 2] {
+    [1.0, 1.0]
+}
+
+/// Pixels of a texture, with colours from 0.0 to 1.0 like `LightColour`s
+struct TextureImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<LightColour>,
+}
+
+impl Default for TextureImage {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            pixels: vec![LightColour::new(1.0, 1.0, 1.0)],
+        }
+    }
+}
+
+impl Debug for TextureImage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureImage")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl From<&DynamicImage> for TextureImage {
+    /// Pixel values are scaled to 0.0 to 1.0 as they are, without decoding
+    /// sRGB, so normal and bump maps keep their values
+    fn from(image: &DynamicImage) -> Self {
+        let image = image.to_rgb32f();
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image
+                .pixels()
+                .map(|p| {
+                    LightColour::new(
+                        p.0[0] as f64,
+                        p.0[1] as f64,
+                        p.0[2] as f64,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ImageTexture {
+    /// A texture using the image at `path`, which isn't read until `load` is
+    /// called
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            wrap: WrapMode::default(),
+            scale: default_scale(),
+            image: Arc::default(),
+        }
+    }
+
+    /// Use `image` for the texture's pixels rather than reading its file.
+    /// Empty images are ignored, leaving the texture as it was
+    pub fn with_image(mut self, image: &DynamicImage) -> Self {
+        if !is_empty(image) {
+            self.image = Arc::new(TextureImage::from(image));
+        }
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_scale(mut self, u: f64, v: f64) -> Self {
+        self.scale = [u, v];
+        self
+    }
+
+    /// Read the image file, relative to `base_dir`. Images with no pixels
+    /// are rejected
+    pub fn load(&mut self, base_dir: &Path) -> ImageResult<()> {
+        let image = image::open(base_dir.join(&self.path))?;
+        if is_empty(&image) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(
+                    "textures need at least one pixel".to_string(),
+                ),
+            )));
+        }
+        self.image = Arc::new(TextureImage::from(&image));
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Change where the image file is, without reloading it
+    pub fn set_path(&mut self, path: impl Into<PathBuf>) {
+        self.path = path.into();
+    }
+
+    pub fn wrap(&self) -> WrapMode {
+        self.wrap
+    }
+
+    /// Colour of the texture at texture coordinates `(u, v)`, where `(0, 0)`
+    /// is the top left corner of the image and `(1, 1)` the bottom right,
+    /// blended between the four nearest pixels
+    pub fn sample(&self, (u, v): (f64, f64)) -> LightColour {
+        let image = &self.image;
+        // Pixel centres are at half coordinates
+        let x = u * self.scale[0] * image.width as f64 - 0.5;
+        let y = v * self.scale[1] * image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |dx: i64, dy: i64| {
+            let i = self.wrap.wrap(x0 as i64 + dx, image.width);
+            let j = self.wrap.wrap(y0 as i64 + dy, image.height);
+            image.pixels[j * image.width + i]
+        };
+        let top = pixel(0, 0) * (1.0 - fx) + pixel(1, 0) * fx;
+        let bottom = pixel(0, 1) * (1.0 - fx) + pixel(1, 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+fn is_empty(image: &DynamicImage) -> bool {
+    image.width() == 0 || image.height() == 0
+}
+
+impl PartialEq for ImageTexture {
+    /// Textures are compared by their settings, not their pixels
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.wrap == other.wrap
+            && self.scale == other.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Material, Microfacet};
+    use image::RgbImage;
+
+    /// A 2x1 texture, black on the left and white on the right
+    fn black_white(wrap: WrapMode) -> ImageTexture {
+        let mut image = RgbImage::new(2, 1);
+        image.put_pixel(1, 0, image::Rgb([255, 255, 255]));
+        ImageTexture::new("")
+            .with_image(&image.into())
+            .with_wrap(wrap)
+    }
+
+    #[test]
+    fn samples_blend_between_pixels() {
+        let texture = black_white(WrapMode::Clamp);
+        assert_eq!(texture.sample((0.25, 0.5)).x, 0.0);
+        assert_eq!(texture.sample((0.75, 0.5)).x, 1.0);
+        assert!((texture.sample((0.5, 0.5)).x - 0.5).abs() < 1e-9);
+        // Clamped past the edges
+        assert_eq!(texture.sample((-3.0, 0.5)).x, 0.0);
+        assert_eq!(texture.sample((3.0, 0.5)).x, 1.0);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let (repeat, mirror) =
+            (black_white(WrapMode::Repeat), black_white(WrapMode::Mirror));
+        assert_eq!(repeat.sample((1.25, 0.5)).x, 0.0);
+        assert_eq!(mirror.sample((1.25, 0.5)).x, 1.0);
+        // Repeating blends the right edge into the left one
+        assert!((repeat.sample((0.0, 0.5)).x - 0.5).abs() < 1e-9);
+        assert_eq!(mirror.sample((0.0, 0.5)).x, 0.0);
+    }
+
+    #[test]
+    fn textures_drive_material() {
+        let material = Material::new(LightColour::new(0.5, 0.5, 0.5), 10.0)
+            .with_microfacet(Microfacet {
+                metallic: 0.0,
+                roughness: 0.8,
+            })
+            .with_albedo_texture(black_white(WrapMode::Clamp))
+            .with_roughness_texture(black_white(WrapMode::Clamp));
+        let point = Point::default();
+        let left = material.at(&point, (0.25, 0.5));
+        let right = material.at(&point, (0.75, 0.5));
+        assert_eq!(left.colour, LightColour::default());
+        assert_eq!(right.colour, material.colour());
+        assert_eq!(left.microfacet.unwrap().roughness, 0.0);
+        assert!((right.microfacet.unwrap().roughness - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unloaded_texture_is_white() {
+        let texture = ImageTexture::new("missing.png");
+        assert_eq!(texture.sample((0.3, 0.7)), LightColour::new(1.0, 1.0, 1.0));
+        assert!(ImageTexture::new("missing.png")
+            .load(Path::new(""))
+            .is_err());
+    }
+
+    #[test]
+    fn empty_images_are_refused() {
+        let empty = RgbImage::new(0, 0).into();
+        let texture = black_white(WrapMode::Clamp).with_image(&empty);
+        assert_eq!(texture.sample((0.75, 0.5)).x, 1.0);
+
+        let dir = std::env::temp_dir().join("ray-tracing-texture-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("empty.hdr"),
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 0\n",
+        )
+        .unwrap();
+        assert!(ImageTexture::new("empty.hdr").load(&dir).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::LightColour;
+use std::str::FromStr;
+
+/// Curve used to squeeze HDR radiance into the 0.0 to 1.0 range a display
+/// can show
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Clip anything brighter than 1.0
+    #[default]
+    Clamp,
+    /// `c / (1 + c)`, which compresses highlights without ever clipping them
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a toe in the shadows and
+    /// a soft shoulder in the highlights
+    Aces,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [Self; 3] = [Self::Clamp, Self::Reinhard, Self::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Clamp => "Clamp",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES Filmic",
+        }
+    }
+
+    fn map_channel(&self, c: f64) -> f64 {
+        let c = c.max(0.0);
+        let mapped = match self {
+            Self::Clamp => c,
+            Self::Reinhard => c / (1.0 + c),
+            Self::Aces => {
+                (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+            }
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    /// Parse an operator from its lowercase name, e.g. "reinhard"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(Self::Clamp),
+            "reinhard" => Ok(Self::Reinhard),
+            "aces" => Ok(Self::Aces),
+            _ => Err(format!(
+                "unknown tone mapping operator '{s}', expected one of \
+                 clamp, reinhard or aces"
+            )),
+        }
+    }
+}
+
+/// Transform from the linear radiance the renderer produces to the colour
+/// values written to the screen or an image file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// Brightness adjustment in stops, applied before the operator. Each
+    /// stop doubles the brightness
+    pub exposure: f64,
+    /// Encode the output with the sRGB transfer curve, which screens and
+    /// image files expect
+    pub srgb: bool,
+}
+
+impl ToneMapping {
+    /// Map a linear radiance to a display colour in the 0.0 to 1.0 range
+    pub fn apply(&self, colour: &LightColour) -> LightColour {
+        let scale = self.exposure.exp2();
+        let map = |c: f64| {
+            let mapped = self.operator.map_channel(c * scale);
+            if self.srgb {
+                srgb_encode(mapped)
+            } else {
+                mapped
+            }
+        };
+        LightColour::new(map(colour.x), map(colour.y), map(colour.z))
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::default(),
+            exposure: 0.0,
+            srgb: true,
+        }
+    }
+}
+
+/// The sRGB transfer function, turning a linear value into one which is
+/// spaced out evenly for the eye
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `srgb_encode`, turning a colour picked by eye back into
+/// linear light
+pub fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(c: f64) -> LightColour {
+        LightColour::new(c, c, c)
+    }
+
+    #[test]
+    fn reinhard_halves_one() {
+        let tone_mapping = ToneMapping {
+            operator: ToneMapOperator::Reinhard,
+            srgb: false,
+            ..ToneMapping::default()
+        };
+        assert_eq!(tone_mapping.apply(&grey(1.0)), grey(0.5));
+    }
+
+    #[test]
+    fn operators_stay_in_display_range() {
+        for operator in ToneMapOperator::ALL {
+            let tone_mapping = ToneMapping {
+                operator,
+                srgb: true,
+                ..ToneMapping::default()
+            };
+            for c in [-1.0, 0.0, 0.5, 1.0, 10.0, 1000.0] {
+                let mapped = tone_mapping.apply(&grey(c)).x;
+                assert!((0.0..=1.0).contains(&mapped), "{operator:?} {c}");
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_is_measured_in_stops() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            srgb: false,
+            ..ToneMapping::default()
+        };
+        assert_eq!(tone_mapping.apply(&grey(0.25)), grey(0.5));
+    }
+
+    #[test]
+    fn srgb_brightens_midtones() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-9);
+        assert!((srgb_encode(0.214) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn srgb_decode_undoes_encode() {
+        for c in [0.0, 0.002, 0.1, 0.5, 1.0] {
+            assert!((srgb_decode(srgb_encode(c)) - c).abs() < 1e-9, "{c}");
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use crate::{srgb_decode, srgb_encode};
+#[cfg(feature = "gtk")]
 use gtk::gdk::RGBA;
 use num::{Bounded, Num, NumCast, ToPrimitive};
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
 pub trait VectorNum:
@@ -37,7 +40,9 @@ pub enum ColourChannel {
     Blue,
 }
 
-#[derive(Default, Debug, Copy, Clone, PartialEq)]
+/// Written as an `[x, y, z]` array in scene files
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[T; 3]", into = "[T; 3]")]
 pub struct Vector<T: VectorNum> {
     pub x: T,
     pub y: T,
@@ -87,7 +92,7 @@ impl<T: VectorNum> Vector<T> {
     /// Multiply two vectors of same type by their values
     /// Note: This is not the cross product
     pub fn mul(&self, rhs: &Self) -> Vector<T> {
-        Vector::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.y)
+        Vector::new(self.x * rhs.x, self.y * rhs.y, self.z * rhs.z)
     }
 
     pub fn colour(&self, colour_channel: &ColourChannel) -> T {
@@ -107,24 +112,54 @@ impl<T: VectorNum> Vector<T> {
     }
 }
 
+impl<T: VectorNum> From<[T; 3]> for Vector<T> {
+    fn from(value: [T; 3]) -> Self {
+        Self::from_array(value)
+    }
+}
+
+impl<T: VectorNum> From<Vector<T>> for [T; 3] {
+    fn from(value: Vector<T>) -> Self {
+        value.to_array()
+    }
+}
+
 impl Vector<u8> {
-    pub fn from_light_colour(light_colour: &LightColour) -> Self {
+    /// Quantise a colour which is already in display space, e.g. one from
+    /// `ToneMapping::apply`, to 8 bits per channel, clipping anything
+    /// outside 0.0 to 1.0
+    pub fn from_display_colour(display_colour: &LightColour) -> Self {
+        let quantise = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
         PixelColour::from_array([
-            (light_colour.x * 255.0) as u8,
-            (light_colour.y * 255.0) as u8,
-            (light_colour.z * 255.0) as u8,
+            quantise(display_colour.x),
+            quantise(display_colour.y),
+            quantise(display_colour.z),
         ])
     }
 
+    /// Encode a linear light colour to sRGB and quantise it, the inverse of
+    /// `to_light_colour`
+    pub fn from_light_colour(light_colour: &LightColour) -> Self {
+        Self::from_display_colour(&LightColour::new(
+            srgb_encode(light_colour.x),
+            srgb_encode(light_colour.y),
+            srgb_encode(light_colour.z),
+        ))
+    }
+
+    /// 8 bit colours are picked by eye, so they're decoded from sRGB to
+    /// linear light
     pub fn to_light_colour(&self) -> LightColour {
+        let decode = |c: u8| srgb_decode(c as f64 / 255.0);
         LightColour::from_array([
-            self.x as f64 / 255.0,
-            self.y as f64 / 255.0,
-            self.z as f64 / 255.0,
+            decode(self.x),
+            decode(self.y),
+            decode(self.z),
         ])
     }
 }
 
+#[cfg(feature = "gtk")]
 impl From<RGBA> for Vector<u8> {
     fn from(value: RGBA) -> Self {
         Self::new(
@@ -146,12 +181,53 @@ impl Vector<f64> {
         }
     }
 
+    /// Two unit vectors at right angles to this one and to each other,
+    /// making a coordinate frame around it. This vector must be normalised
+    pub fn orthonormal_basis(&self) -> (Vector<f64>, Vector<f64>) {
+        // Duff et al., "Building an Orthonormal Basis, Revisited"
+        let sign = 1.0_f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vector::new(
+                1.0 + sign * self.x * self.x * a,
+                sign * b,
+                -sign * self.x,
+            ),
+            Vector::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     /// Calculate the cosine of the degree between two vectors
     pub fn cosine_angle(&self, other: &Vector<f64>) -> f64 {
         // 9.0 is the product of both vector lengths
         self.dot(other) / 9.0
     }
 
+    /// Reflect a direction about a surface normal, i.e. the direction a ray
+    /// travelling along `self` bounces off in
+    pub fn reflect(&self, normal: &Vector<f64>) -> Vector<f64> {
+        *self - (*normal * (2.0 * self.dot(normal)))
+    }
+
+    /// Bend a unit direction as it passes through a surface, following
+    /// Snell's law. `normal` must face against `self` and `eta` is the ratio
+    /// of the refractive index being left to the one being entered. Returns
+    /// `None` when the ray is totally internally reflected instead
+    pub fn refract(
+        &self,
+        normal: &Vector<f64>,
+        eta: f64,
+    ) -> Option<Vector<f64>> {
+        let cos_i = -self.dot(normal);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            return None;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        Some(*self * eta + *normal * (eta * cos_i - cos_t))
+    }
+
     pub fn invert(&self) -> Vector<f64> {
         Self::new(
             if self.x != 0.0 { 1.0 / self.x } else { 0.0 },
@@ -204,6 +280,32 @@ pub fn id_matrix() -> Matrix3x3<f64> {
     ]
 }
 
+/// Matrix rotating anticlockwise by `degrees` around `axis`, looking back
+/// along the axis towards the origin. `axis` doesn't need to be normalised
+pub fn rotation_matrix(axis: Vector<f64>, degrees: f64) -> Matrix3x3<f64> {
+    let mut k = axis;
+    k.normalise();
+    let (s, c) = degrees.to_radians().sin_cos();
+    let t = 1.0 - c;
+    [
+        Vector::new(
+            c + k.x * k.x * t,
+            k.x * k.y * t + k.z * s,
+            k.x * k.z * t - k.y * s,
+        ),
+        Vector::new(
+            k.x * k.y * t - k.z * s,
+            c + k.y * k.y * t,
+            k.y * k.z * t + k.x * s,
+        ),
+        Vector::new(
+            k.x * k.z * t + k.y * s,
+            k.y * k.z * t - k.x * s,
+            c + k.z * k.z * t,
+        ),
+    ]
+}
+
 impl<T: VectorNum> std::ops::Mul<Vector<T>> for Vector<T> {
     type Output = Self;
 
@@ -317,7 +419,23 @@ impl<T: VectorNum> Display for Vector<T> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{id_matrix, matrix_mul, Vector};
+    use crate::{
+        id_matrix, matrix_mul, rotation_matrix, PixelColour, ToneMapping,
+        Vector, BURNT_ORANGE,
+    };
+
+    #[test]
+    fn picked_colours_come_back_out_unchanged() {
+        let linear = BURNT_ORANGE.to_light_colour();
+        assert!(linear.x < 204.0 / 255.0 && linear.y < 85.0 / 255.0);
+        let shown = ToneMapping::default().apply(&linear);
+        assert_eq!(PixelColour::from_display_colour(&shown), BURNT_ORANGE);
+        for c in 0..=255 {
+            let colour = PixelColour::new(c, c, c);
+            let linear = colour.to_light_colour();
+            assert_eq!(PixelColour::from_light_colour(&linear), colour);
+        }
+    }
 
     #[test]
     fn cross_product() {
@@ -366,6 +484,31 @@ mod tests {
         assert_eq!(v.magnitude(), 3.0);
     }
 
+    #[test]
+    fn reflection() {
+        let v = Vector::new(1.0, -1.0, 0.0);
+        let normal = Vector::new(0.0, 1.0, 0.0);
+        assert_eq!(v.reflect(&normal), Vector::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn refraction_bends_towards_normal() {
+        let v = Vector::new(1.0, -1.0, 0.0) * std::f64::consts::FRAC_1_SQRT_2;
+        let normal = Vector::new(0.0, 1.0, 0.0);
+        let refracted = v.refract(&normal, 1.0 / 1.5).unwrap();
+        // sin(45) / 1.5
+        assert!((refracted.x - 0.4714).abs() < 1e-4);
+        assert!(refracted.y < 0.0);
+        assert!((refracted.magnitude() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn refraction_totally_internally_reflects() {
+        let v = Vector::new(1.0, -1.0, 0.0) * std::f64::consts::FRAC_1_SQRT_2;
+        let normal = Vector::new(0.0, 1.0, 0.0);
+        assert!(v.refract(&normal, 1.5).is_none());
+    }
+
     #[test]
     fn dot_product() {
         let v1 = Vector::new(1.0, 2.0, 3.0);
@@ -410,4 +553,31 @@ mod tests {
         assert_eq!(matrix_mul(id_matrix(), matrix2), matrix2);
         assert_eq!(matrix_mul(matrix2, id_matrix()), matrix2);
     }
+
+    #[test]
+    fn orthonormal_basis_is_perpendicular() {
+        for mut v in [
+            Vector::new(0.0, 0.0, 1.0),
+            Vector::new(0.0, 0.0, -1.0),
+            Vector::new(1.0, -2.0, 3.0),
+        ] {
+            v.normalise();
+            let (t, b) = v.orthonormal_basis();
+            for (x, y) in [(v, t), (v, b), (t, b)] {
+                assert!(x.dot(&y).abs() < 1e-12);
+            }
+            assert!((t.magnitude() - 1.0).abs() < 1e-12);
+            assert!((b.magnitude() - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rotation_about_axis() {
+        let rotation = rotation_matrix(Vector::new(0.0, 2.0, 0.0), 90.0);
+        let rotated = rotation * Vector::new(0.0, 0.0, 1.0);
+        assert!((rotated - Vector::new(1.0, 0.0, 0.0)).magnitude() < 1e-12);
+        // Points on the axis don't move
+        let on_axis = Vector::new(0.0, 3.0, 0.0);
+        assert!((rotation * on_axis - on_axis).magnitude() < 1e-12);
+    }
 }